@@ -0,0 +1,117 @@
+// Shows a Rust host driving glang through the Interpreter API, run with `cargo run --example embedding`
+use glang::{GlangError, HostObject, Interpreter, RuntimeVal, RuntimeValType};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+// A host object scripts can hold a handle to, its health changes through a Cell because userdata is shared
+struct Entity {
+    id: i64,
+    health: Cell<i64>
+}
+impl HostObject for Entity {
+    fn type_name(&self) -> &str {
+        "Entity"
+    }
+
+    fn get_field(&self, name: &str) -> Result<RuntimeVal, GlangError> {
+        match name {
+            "id" => Ok(self.id.into()),
+            "health" => Ok(self.health.get().into()),
+            _ => Err(GlangError::runtime(format!("Entity has no field {:?}", name)).with_code("E0307"))
+        }
+    }
+
+    fn set_field(&self, name: &str, value: RuntimeVal) -> Result<(), GlangError> {
+        match name {
+            "health" => {
+                self.health.set(value.expect_int()?);
+                Ok(())
+            },
+            _ => Err(GlangError::runtime(format!("Cannot assign field {:?} of Entity", name)).with_code("E0307"))
+        }
+    }
+
+    fn call_method(&self, name: &str, arguments: &[RuntimeVal]) -> Result<RuntimeVal, GlangError> {
+        match (name, arguments) {
+            ("damage", [amount]) => {
+                self.health.set((self.health.get() - amount.expect_int()?).max(0));
+                Ok(self.health.get().into())
+            },
+            _ => Err(GlangError::runtime(format!("Entity has no method {:?} taking {} argument(s)", name, arguments.len())).with_code("E0307"))
+        }
+    }
+
+    fn display(&self) -> String {
+        format!("Entity#{} ({} hp)", self.id, self.health.get())
+    }
+
+    // Two handles are equal when they refer to the same id
+    fn equals(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Entity>().is_some_and(|other| other.id == self.id)
+    }
+}
+
+fn main() -> Result<(), GlangError> {
+    let mut interpreter = Interpreter::new();
+
+    // Globals set by the host are visible to every snippet
+    interpreter.set_global("base", 10.into());
+
+    // Native functions are called like any other function and check their own argument types
+    let metrics = Rc::new(RefCell::new(Vec::new()));
+    let recorded = metrics.clone();
+    interpreter.register_function("emit_metric", Some(2), move |arguments| {
+        recorded.borrow_mut().push((arguments[0].expect_str()?.to_string(), arguments[1].expect_float()?));
+        Ok(RuntimeVal::null())
+    });
+    interpreter.eval("
+        func scale(x) {
+            return x * base
+        }
+        let calls = 0
+    ")?;
+
+    // State persists between snippets
+    interpreter.eval("calls = calls + 1")?;
+    let result = interpreter.call("scale", &[4.into()])?;
+    if let RuntimeValType::NumericInteger(value) = result.runtime_val_type {
+        println!("scale(4) = {}", value);
+    }
+
+    let calls = interpreter.get_global("calls").unwrap();
+    println!("calls = {}", calls.repr());
+
+    interpreter.eval("emit_metric(\"latency\", scale(2))")?;
+    println!("metrics = {:?}", metrics.borrow());
+    if let Err(error) = interpreter.eval("emit_metric(1, 2)") {
+        println!("{}", error);
+    }
+
+    // Scripts can read, write and call into host objects, and the host sees the changes
+    let player = RuntimeVal::userdata(Entity { id: 1, health: Cell::new(100) });
+    interpreter.set_global("player", player.clone());
+    interpreter.set_global("same_player", RuntimeVal::userdata(Entity { id: 1, health: Cell::new(100) }));
+    interpreter.eval("
+        player.damage(30)
+        player.health = player.health - 5
+        print(player)
+        print(player == same_player)
+    ")?;
+    println!("health seen by the host = {}", player.expect_userdata::<Entity>()?.health.get());
+
+    // print can be captured instead of going to stdout, eprint has its own sink
+    let captured: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(captured.clone());
+    interpreter.eval("
+        print(scale(3))
+        eprint(\"scale is linear\")
+    ")?;
+    println!("captured = {:?}", String::from_utf8_lossy(&captured.borrow()));
+
+    // Errors come back as values instead of ending the host
+    if let Err(error) = interpreter.eval("scale(1, 2)") {
+        println!("{}", error);
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,418 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+// Arbitrary precision integer that integer arithmetic grows into when it overflows i64.
+// Stored as sign and magnitude, the magnitude in base 2^32 with the least significant limb first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32> // No trailing zero limbs, so zero is empty and never negative
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt { negative: negative && !magnitude.is_empty(), magnitude }
+    }
+
+    pub fn from_i64(value: i64) -> BigInt {
+        let unsigned = value.unsigned_abs();
+        BigInt::from_parts(value < 0, vec![unsigned as u32, (unsigned >> 32) as u32])
+    }
+
+    // Parses an optional minus sign followed by decimal digits
+    pub fn parse(text: &str) -> Option<BigInt> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text)
+        };
+        if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return None
+        }
+        let mut magnitude: Vec<u32> = vec![];
+        for digit in digits.bytes() {
+            mul_small_add(&mut magnitude, 10, (digit - b'0') as u32);
+        }
+        Some(BigInt::from_parts(negative, magnitude))
+    }
+
+    // Gives the value back as an i64 when it fits
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None
+        }
+        let unsigned = self.magnitude.iter().rev().fold(0u64, |acc, limb| (acc << 32) | *limb as u64);
+        if self.negative {
+            // i64::MIN has no positive counterpart, so it is checked against the magnitude
+            (unsigned <= 1 << 63).then(|| (unsigned as i64).wrapping_neg())
+        } else {
+            i64::try_from(unsigned).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let unsigned = self.magnitude.iter().rev().fold(0.0, |acc, limb| acc * 4294967296.0 + *limb as f64);
+        if self.negative { -unsigned } else { unsigned }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    // Number of bits in the magnitude, zero for zero
+    pub fn bit_length(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(top) => self.magnitude.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0
+        }
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    // Truncating division, the remainder takes the sign of the dividend like i64's / and %.
+    // None when dividing by zero
+    pub fn div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None
+        }
+        let (quotient, remainder) = div_rem_magnitude(&self.magnitude, &divisor.magnitude);
+        Some((BigInt::from_parts(self.negative != divisor.negative, quotient), BigInt::from_parts(self.negative, remainder)))
+    }
+}
+
+fn compare_magnitude(left: &[u32], right: &[u32]) -> Ordering {
+    left.len().cmp(&right.len()).then_with(|| left.iter().rev().cmp(right.iter().rev()))
+}
+
+fn add_magnitude(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(left.len().max(right.len()) + 1);
+    let mut carry = 0u64;
+    for position in 0..left.len().max(right.len()) {
+        let sum = *left.get(position).unwrap_or(&0) as u64 + *right.get(position).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    result.push(carry as u32);
+    result
+}
+
+// The left magnitude must be at least as large as the right
+fn sub_magnitude(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(left.len());
+    let mut borrow = 0i64;
+    for (position, limb) in left.iter().enumerate() {
+        let mut difference = *limb as i64 - *right.get(position).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if difference < 0 {
+            difference += 1 << 32;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    result
+}
+
+fn mul_magnitude(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; left.len() + right.len()];
+    for (i, left_limb) in left.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, right_limb) in right.iter().enumerate() {
+            let product = *left_limb as u64 * *right_limb as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + right.len()] = carry as u32;
+    }
+    result
+}
+
+fn mul_small_add(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for limb in magnitude.iter_mut() {
+        let product = *limb as u64 * factor as u64 + carry;
+        *limb = product as u32;
+        carry = product >> 32;
+    }
+    if carry > 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+// Divides in place and gives the remainder
+fn div_small(magnitude: &mut [u32], divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for limb in magnitude.iter_mut().rev() {
+        let current = (remainder << 32) | *limb as u64;
+        *limb = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    remainder as u32
+}
+
+// Long division a limb at a time, Knuth's algorithm D (The Art of Computer Programming vol. 2, 4.3.1)
+fn div_rem_magnitude(dividend: &[u32], divisor: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if compare_magnitude(dividend, divisor) == Ordering::Less {
+        return (vec![], dividend.to_vec())
+    }
+    if divisor.len() == 1 {
+        let mut quotient = dividend.to_vec();
+        let remainder = div_small(&mut quotient, divisor[0]);
+        return (quotient, vec![remainder])
+    }
+    // Normalise so the top limb of the divisor has its high bit set, which keeps each estimated quotient limb at most 2 too large
+    let shift = divisor.last().unwrap().leading_zeros();
+    let divisor = shift_left(divisor, shift);
+    let mut remainder = shift_left(dividend, shift);
+    remainder.push(0);
+    let length = divisor.len();
+    let top = divisor[length - 1] as u64;
+    let second = divisor[length - 2] as u64;
+    let mut quotient = vec![0u32; remainder.len() - length];
+
+    for position in (0..quotient.len()).rev() {
+        // Estimate the quotient limb from the top two limbs of the remainder, then correct it with the next one
+        let window = ((remainder[position + length] as u64) << 32) | remainder[position + length - 1] as u64;
+        let mut estimate = window / top;
+        let mut partial = window % top;
+        while estimate > u32::MAX as u64 || estimate * second > ((partial << 32) | remainder[position + length - 2] as u64) {
+            estimate -= 1;
+            partial += top;
+            if partial > u32::MAX as u64 {
+                break
+            }
+        }
+
+        // Subtract estimate * divisor from the window of the remainder
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for (offset, limb) in divisor.iter().enumerate() {
+            let product = estimate * *limb as u64 + carry;
+            carry = product >> 32;
+            let difference = remainder[position + offset] as i64 - (product & 0xFFFF_FFFF) as i64 - borrow;
+            remainder[position + offset] = difference as u32;
+            borrow = (difference < 0) as i64;
+        }
+        let difference = remainder[position + length] as i64 - carry as i64 - borrow;
+        remainder[position + length] = difference as u32;
+
+        // Rarely the estimate is still one too large and the remainder goes negative, so the divisor is added back
+        if difference < 0 {
+            estimate -= 1;
+            let mut carry = 0u64;
+            for (offset, limb) in divisor.iter().enumerate() {
+                let sum = remainder[position + offset] as u64 + *limb as u64 + carry;
+                remainder[position + offset] = sum as u32;
+                carry = sum >> 32;
+            }
+            remainder[position + length] = remainder[position + length].wrapping_add(carry as u32);
+        }
+        quotient[position] = estimate as u32;
+    }
+
+    remainder.truncate(length);
+    (quotient, shift_right(&remainder, shift))
+}
+
+fn shift_left(magnitude: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return magnitude.to_vec()
+    }
+    let mut result = Vec::with_capacity(magnitude.len() + 1);
+    let mut carry = 0u32;
+    for limb in magnitude {
+        result.push((limb << shift) | carry);
+        carry = limb >> (32 - shift);
+    }
+    if carry > 0 {
+        result.push(carry);
+    }
+    result
+}
+
+fn shift_right(magnitude: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return magnitude.to_vec()
+    }
+    let mut result = vec![0u32; magnitude.len()];
+    for position in 0..magnitude.len() {
+        let high = magnitude.get(position + 1).map_or(0, |limb| limb << (32 - shift));
+        result[position] = (magnitude[position] >> shift) | high;
+    }
+    result
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitude(&self.magnitude, &other.magnitude))
+        }
+        // Opposite signs, the larger magnitude decides the sign of the result
+        match compare_magnitude(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_magnitude(&other.magnitude, &self.magnitude)),
+            _ => BigInt::from_parts(self.negative, sub_magnitude(&self.magnitude, &other.magnitude))
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != other.negative, mul_magnitude(&self.magnitude, &other.magnitude))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitude(&other.magnitude, &self.magnitude)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Peels off nine decimal digits at a time, least significant first
+        let mut magnitude = self.magnitude.clone();
+        let mut chunks: Vec<u32> = vec![];
+        while !magnitude.is_empty() {
+            chunks.push(div_small(&mut magnitude, 1_000_000_000));
+            while magnitude.last() == Some(&0) {
+                magnitude.pop();
+            }
+        }
+        let mut text = String::new();
+        match chunks.split_last() {
+            Some((most_significant, rest)) => {
+                text.push_str(&most_significant.to_string());
+                for chunk in rest.iter().rev() {
+                    text.push_str(&format!("{:09}", chunk));
+                }
+            },
+            None => text.push('0')
+        }
+        f.pad_integral(!self.negative, "", &text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).expect("valid integer")
+    }
+
+    #[test]
+    fn parse_reads_signed_decimal() {
+        assert_eq!(big("0").to_string(), "0");
+        assert_eq!(big("-0"), BigInt::from_i64(0));
+        assert_eq!(big("000123").to_string(), "123");
+        assert_eq!(big("-98765432109876543210987654321").to_string(), "-98765432109876543210987654321");
+        assert_eq!(BigInt::parse(""), None);
+        assert_eq!(BigInt::parse("-"), None);
+        assert_eq!(BigInt::parse("12a"), None);
+        assert_eq!(BigInt::parse("+5"), None);
+    }
+
+    #[test]
+    fn to_i64_handles_the_extremes() {
+        assert_eq!(big("9223372036854775807").to_i64(), Some(i64::MAX));
+        assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").to_i64(), None);
+        assert_eq!(BigInt::from_i64(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(BigInt::from_i64(i64::MAX).to_i64(), Some(i64::MAX));
+    }
+
+    #[test]
+    fn div_rem_truncates_like_i64() {
+        for (dividend, divisor) in [(7, 2), (-7, 2), (7, -2), (-7, -2), (6, 3), (-6, 3), (1, 5), (-1, 5), (0, -4)] {
+            let (quotient, remainder) = BigInt::from_i64(dividend).div_rem(&BigInt::from_i64(divisor)).unwrap();
+            assert_eq!((quotient.to_i64(), remainder.to_i64()), (Some(dividend / divisor), Some(dividend % divisor)), "{} / {}", dividend, divisor);
+        }
+        assert_eq!(BigInt::from_i64(1).div_rem(&BigInt::from_i64(0)), None);
+    }
+
+    #[test]
+    fn div_rem_of_multi_limb_values() {
+        let (quotient, remainder) = big("-123456789012345678901234567890123456789").div_rem(&big("98765432109876543210")).unwrap();
+        assert_eq!(quotient.to_string(), "-1249999988609375000");
+        assert_eq!(remainder.to_string(), "-15297067891529706789");
+        // A divisor whose top limb is all ones makes the first quotient estimate too large
+        let divisor = big("79228162514264337593543950335"); // 2^96 - 1
+        let dividend = &(&divisor * &big("340282366920938463463374607431768211455")) + &big("12345");
+        let (quotient, remainder) = dividend.div_rem(&divisor).unwrap();
+        assert_eq!(quotient.to_string(), "340282366920938463463374607431768211455");
+        assert_eq!(remainder.to_string(), "12345");
+    }
+
+    #[test]
+    fn div_rem_reconstructs_the_dividend() {
+        let mut dividend = big("1");
+        let divisor = &big("3").pow(61) - &big("1");
+        for step in 0..40 {
+            dividend = &(&dividend * &big("4294967311")) + &BigInt::from_i64(step);
+            let (quotient, remainder) = dividend.div_rem(&divisor).unwrap();
+            assert_eq!(&(&quotient * &divisor) + &remainder, dividend);
+            assert!(compare_magnitude(&remainder.magnitude, &divisor.magnitude) == Ordering::Less);
+        }
+    }
+
+    #[test]
+    fn arithmetic_crosses_limb_boundaries() {
+        let limb = big("4294967296");
+        assert_eq!((&limb * &limb).to_string(), "18446744073709551616");
+        assert_eq!((&(&limb * &limb) - &big("1")).to_string(), "18446744073709551615");
+        assert_eq!((&big("-5") + &big("3")).to_string(), "-2");
+        assert_eq!(big("2").pow(100).bit_length(), 101);
+        assert!(big("-10") < big("-9"));
+    }
+}
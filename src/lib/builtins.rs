@@ -0,0 +1,388 @@
+use super::eval;
+use super::environment;
+use super::error::GlangError;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+// Longest string repeat may build, in bytes, so a huge count fails instead of exhausting memory
+const MAX_REPEAT_LENGTH: usize = 1 << 24;
+// Largest width or precision a format specifier may ask for, for the same reason
+const MAX_FORMAT_WIDTH: usize = 1 << 16;
+
+fn expect_arguments(name: &str, arguments: &[eval::RuntimeVal], count: usize) -> Result<(), GlangError> {
+    if arguments.len() != count {
+        return Err(GlangError::runtime(format!("Builtin {:?} expects {} argument(s), got {}", name, count, arguments.len())).with_code("E0305"))
+    }
+    Ok(())
+}
+
+fn expect_array(name: &str, value: &eval::RuntimeVal) -> Result<Rc<RefCell<Vec<eval::RuntimeVal>>>, GlangError> {
+    match value.runtime_val_type.extract_array_value() {
+        Some(elements) => Ok(elements.clone()),
+        None => Err(GlangError::runtime(format!("Builtin {:?} expects an array, got {}", name, value.runtime_val_type.type_name())).with_code("E0302"))
+    }
+}
+
+fn expect_map(name: &str, value: &eval::RuntimeVal) -> Result<Rc<RefCell<BTreeMap<eval::MapKey, eval::RuntimeVal>>>, GlangError> {
+    match value.runtime_val_type.extract_map_value() {
+        Some(entries) => Ok(entries.clone()),
+        None => Err(GlangError::runtime(format!("Builtin {:?} expects a map, got {}", name, value.runtime_val_type.type_name())).with_code("E0302"))
+    }
+}
+
+fn expect_string<'a>(name: &str, value: &'a eval::RuntimeVal) -> Result<&'a str, GlangError> {
+    match &value.runtime_val_type {
+        eval::RuntimeValType::StringLiteral(string_) => Ok(string_),
+        other => Err(GlangError::runtime(format!("Builtin {:?} expects a string, got {}", name, other.type_name())).with_code("E0302"))
+    }
+}
+
+fn position_index(index: &eval::RuntimeVal, length: usize) -> Result<usize, GlangError> {
+    // A position between elements, so unlike an element index it may equal the length
+    match index.runtime_val_type {
+        eval::RuntimeValType::NumericInteger(value) if value >= 0 && value as usize == length => Ok(length),
+        _ => eval::array_index(index, length)
+    }
+}
+
+fn string(text: String) -> eval::RuntimeVal {
+    eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::StringLiteral(text) }
+}
+
+fn boolean(value: bool) -> eval::RuntimeVal {
+    eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Boolean(value) }
+}
+
+fn array(elements: Vec<eval::RuntimeVal>) -> eval::RuntimeVal {
+    eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Array(Rc::new(RefCell::new(elements))) }
+}
+
+fn null() -> eval::RuntimeVal {
+    eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Null }
+}
+
+fn invalid_spec(spec: &str) -> GlangError {
+    GlangError::runtime(format!("Invalid format specifier {:?}", spec)).with_help("specifiers look like `{:>8.2}`, with an optional fill and alignment (<, > or ^), a width and a precision".to_string())
+}
+
+fn format_value(value: &eval::RuntimeVal, spec: &str) -> Result<String, GlangError> {
+    // The specifier is [[fill]align][width][.precision], numbers are right aligned by default and everything else left
+    let characters: Vec<char> = spec.chars().collect();
+    let (fill, align, rest) = match characters.as_slice() {
+        [fill, align @ ('<' | '>' | '^'), rest @ ..] => (*fill, Some(*align), rest),
+        [align @ ('<' | '>' | '^'), rest @ ..] => (' ', Some(*align), rest),
+        rest => (' ', None, rest)
+    };
+    let rest: String = rest.iter().collect();
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision.parse::<usize>().map_err(|_| invalid_spec(spec))?)),
+        None => (rest.as_str(), None)
+    };
+    let width = if width.is_empty() { 0 } else { width.parse::<usize>().map_err(|_| invalid_spec(spec))? };
+    if width > MAX_FORMAT_WIDTH || precision.is_some_and(|precision| precision > MAX_FORMAT_WIDTH) {
+        return Err(invalid_spec(spec).with_help(format!("width and precision can be at most {}", MAX_FORMAT_WIDTH)))
+    }
+
+    let numeric = matches!(value.runtime_val_type, eval::RuntimeValType::NumericInteger(_) | eval::RuntimeValType::BigInteger(_) | eval::RuntimeValType::NumericFloat(_));
+    let text = match (&value.runtime_val_type, precision) {
+        (eval::RuntimeValType::NumericInteger(int), Some(precision)) => format!("{:.*}", precision, *int as f64),
+        (eval::RuntimeValType::BigInteger(big), Some(0)) => big.to_string(),
+        (eval::RuntimeValType::BigInteger(big), Some(precision)) => format!("{}.{}", big, "0".repeat(precision)), // Exact, a float would lose digits
+        (eval::RuntimeValType::NumericFloat(float), Some(precision)) => format!("{:.*}", precision, float),
+        (eval::RuntimeValType::StringLiteral(string_), Some(precision)) => string_.chars().take(precision).collect(), // Precision truncates strings
+        (other, Some(_)) => return Err(GlangError::runtime(format!("Precision is not supported for {}", other.type_name())).with_code("E0302")),
+        (_, None) => value.to_string()
+    };
+
+    let padding = width.saturating_sub(text.chars().count());
+    let (left, right) = match align.unwrap_or(if numeric { '>' } else { '<' }) {
+        '<' => (0, padding),
+        '>' => (padding, 0),
+        _ => (padding / 2, padding - padding / 2)
+    };
+    Ok(format!("{}{}{}", fill.to_string().repeat(left), text, fill.to_string().repeat(right)))
+}
+
+fn format_template(template: &str, values: &[eval::RuntimeVal]) -> Result<String, GlangError> {
+    // Each {} or {:spec} takes the next value, {{ and }} are literal braces
+    let mut output = String::new();
+    let mut placeholders = 0;
+    let mut characters = template.chars().peekable();
+    while let Some(character) = characters.next() {
+        if (character == '{' || character == '}') && characters.peek() == Some(&character) {
+            characters.next();
+            output.push(character);
+        } else if character == '{' {
+            let mut placeholder = String::new();
+            loop {
+                match characters.next() {
+                    Some('}') => break,
+                    Some(character) => placeholder.push(character),
+                    None => return Err(GlangError::runtime(format!("Unclosed placeholder in format template {:?}", template)))
+                }
+            }
+            let spec = match placeholder.strip_prefix(':') {
+                Some(spec) => spec,
+                None if placeholder.is_empty() => "",
+                None => return Err(invalid_spec(&placeholder))
+            };
+            if let Some(value) = values.get(placeholders) {
+                output.push_str(&format_value(value, spec)?);
+            }
+            placeholders += 1;
+        } else {
+            output.push(character);
+        }
+    }
+    if placeholders != values.len() {
+        return Err(GlangError::runtime(format!("Format template has {} placeholder(s), got {} value(s)", placeholders, values.len())).with_code("E0305"))
+    }
+    Ok(output)
+}
+
+pub const BUILTINS: [&str; 23] = [
+    "len", "push", "pop", "insert", "remove", "slice", "keys", "values", "has", "delete", "eprint", "format",
+    "upper", "lower", "trim", "split", "join", "replace", "contains", "starts_with", "ends_with", "find", "repeat"
+];
+
+// Returns None when there is no builtin with the given name. The environment is only used to find output sinks
+pub fn call_builtin(name: &str, arguments: &[eval::RuntimeVal], env: Rc<RefCell<environment::Environment>>) -> Result<Option<eval::RuntimeVal>, GlangError> {
+    let result = match name {
+        "len" => {
+            expect_arguments(name, arguments, 1)?;
+            let length = match &arguments[0].runtime_val_type {
+                eval::RuntimeValType::Map(entries) => entries.borrow().len(),
+                eval::RuntimeValType::StringLiteral(string_) => string_.chars().count(), // Characters, not bytes
+                _ => expect_array(name, &arguments[0])?.borrow().len()
+            };
+            eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::NumericInteger(length as i64) }
+        },
+        "push" => {
+            expect_arguments(name, arguments, 2)?;
+            let elements = expect_array(name, &arguments[0])?;
+            elements.borrow_mut().push(arguments[1].clone());
+            null()
+        },
+        "pop" => {
+            expect_arguments(name, arguments, 1)?;
+            let elements = expect_array(name, &arguments[0])?;
+            let popped = elements.borrow_mut().pop();
+            match popped {
+                Some(value) => value,
+                None => return Err(GlangError::runtime("Cannot pop from an empty array".to_string()))
+            }
+        },
+        "insert" => {
+            expect_arguments(name, arguments, 3)?;
+            let elements = expect_array(name, &arguments[0])?;
+            let length = elements.borrow().len();
+            let index = position_index(&arguments[1], length)?; // Inserting at the end is allowed
+            elements.borrow_mut().insert(index, arguments[2].clone());
+            null()
+        },
+        "remove" => {
+            expect_arguments(name, arguments, 2)?;
+            let elements = expect_array(name, &arguments[0])?;
+            let length = elements.borrow().len();
+            let index = eval::array_index(&arguments[1], length)?;
+            elements.borrow_mut().remove(index)
+        },
+        "slice" => {
+            expect_arguments(name, arguments, 3)?;
+            if let eval::RuntimeValType::StringLiteral(string_) = &arguments[0].runtime_val_type {
+                let characters: Vec<char> = string_.chars().collect();
+                let start = position_index(&arguments[1], characters.len())?;
+                let end = position_index(&arguments[2], characters.len())?;
+                if start > end {
+                    return Err(GlangError::runtime(format!("Slice start {} is greater than slice end {}", start, end)))
+                }
+                return Ok(Some(string(characters[start..end].iter().collect())))
+            }
+            let elements = expect_array(name, &arguments[0])?;
+            let length = elements.borrow().len();
+            let start = position_index(&arguments[1], length)?;
+            let end = position_index(&arguments[2], length)?;
+            if start > end {
+                return Err(GlangError::runtime(format!("Slice start {} is greater than slice end {}", start, end)))
+            }
+            let sliced = elements.borrow()[start..end].to_vec();
+            array(sliced)
+        },
+        "keys" => {
+            expect_arguments(name, arguments, 1)?;
+            let entries = expect_map(name, &arguments[0])?;
+            let keys = entries.borrow().keys().map(|key| key.to_value()).collect();
+            array(keys)
+        },
+        "values" => {
+            expect_arguments(name, arguments, 1)?;
+            let entries = expect_map(name, &arguments[0])?;
+            let values = entries.borrow().values().cloned().collect();
+            array(values)
+        },
+        "has" => {
+            expect_arguments(name, arguments, 2)?;
+            let entries = expect_map(name, &arguments[0])?;
+            let found = entries.borrow().contains_key(&eval::MapKey::from_value(&arguments[1])?);
+            eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Boolean(found) }
+        },
+        "delete" => {
+            expect_arguments(name, arguments, 2)?;
+            let entries = expect_map(name, &arguments[0])?;
+            let key = eval::MapKey::from_value(&arguments[1])?;
+            let removed = entries.borrow_mut().remove(&key);
+            match removed {
+                Some(value) => value,
+                None => return Err(GlangError::runtime(format!("Key not found in map - {}", arguments[1].repr())).with_code("E0304"))
+            }
+        },
+        "eprint" => {
+            // Formats like print, without the sep and end named arguments
+            let values: Vec<String> = arguments.iter().map(|value| value.to_string()).collect();
+            environment::write_output(env, environment::Stream::Error, &format!("{}\n", values.join(" ")))?;
+            null()
+        },
+        "format" => {
+            let Some((template, values)) = arguments.split_first() else {
+                return Err(GlangError::runtime(format!("Builtin {:?} expects a template, got no arguments", name)).with_code("E0305"))
+            };
+            let text = format_template(template.expect_str()?, values)?;
+            eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::StringLiteral(text) }
+        },
+        "upper" => {
+            expect_arguments(name, arguments, 1)?;
+            string(expect_string(name, &arguments[0])?.to_uppercase())
+        },
+        "lower" => {
+            expect_arguments(name, arguments, 1)?;
+            string(expect_string(name, &arguments[0])?.to_lowercase())
+        },
+        "trim" => {
+            expect_arguments(name, arguments, 1)?;
+            string(expect_string(name, &arguments[0])?.trim().to_string())
+        },
+        "split" => {
+            // Without a separator the string is split on runs of whitespace, an empty separator splits it into characters
+            let parts: Vec<eval::RuntimeVal> = match arguments {
+                [text] => expect_string(name, text)?.split_whitespace().map(|part| string(part.to_string())).collect(),
+                [text, separator] => {
+                    let (text, separator) = (expect_string(name, text)?, expect_string(name, separator)?);
+                    if separator.is_empty() {
+                        text.chars().map(|character| string(character.to_string())).collect()
+                    } else {
+                        text.split(separator).map(|part| string(part.to_string())).collect()
+                    }
+                },
+                _ => return Err(GlangError::runtime(format!("Builtin {:?} expects 1 or 2 argument(s), got {}", name, arguments.len())).with_code("E0305"))
+            };
+            array(parts)
+        },
+        "join" => {
+            // Elements that are not strings are joined the way print shows them
+            expect_arguments(name, arguments, 2)?;
+            let elements = expect_array(name, &arguments[0])?;
+            let separator = expect_string(name, &arguments[1])?;
+            let parts: Vec<String> = elements.borrow().iter().map(|element| element.to_string()).collect();
+            string(parts.join(separator))
+        },
+        "replace" => {
+            expect_arguments(name, arguments, 3)?;
+            let text = expect_string(name, &arguments[0])?;
+            string(text.replace(expect_string(name, &arguments[1])?, expect_string(name, &arguments[2])?))
+        },
+        "contains" => {
+            expect_arguments(name, arguments, 2)?;
+            boolean(expect_string(name, &arguments[0])?.contains(expect_string(name, &arguments[1])?))
+        },
+        "starts_with" => {
+            expect_arguments(name, arguments, 2)?;
+            boolean(expect_string(name, &arguments[0])?.starts_with(expect_string(name, &arguments[1])?))
+        },
+        "ends_with" => {
+            expect_arguments(name, arguments, 2)?;
+            boolean(expect_string(name, &arguments[0])?.ends_with(expect_string(name, &arguments[1])?))
+        },
+        "find" => {
+            // Gives the character index of the first match, or -1 when there is none
+            expect_arguments(name, arguments, 2)?;
+            let text = expect_string(name, &arguments[0])?;
+            let position = match text.find(expect_string(name, &arguments[1])?) {
+                Some(byte_index) => text[..byte_index].chars().count() as i64,
+                None => -1
+            };
+            eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::NumericInteger(position) }
+        },
+        "repeat" => {
+            expect_arguments(name, arguments, 2)?;
+            let text = expect_string(name, &arguments[0])?;
+            match arguments[1].runtime_val_type {
+                eval::RuntimeValType::NumericInteger(count) if count >= 0 => {
+                    match usize::try_from(count).ok().and_then(|count| text.len().checked_mul(count)) {
+                        Some(length) if length <= MAX_REPEAT_LENGTH => string(text.repeat(count as usize)),
+                        _ => return Err(GlangError::runtime(format!("Cannot repeat a string of {} bytes {} times, the result would be over {} bytes", text.len(), count, MAX_REPEAT_LENGTH)))
+                    }
+                },
+                eval::RuntimeValType::NumericInteger(count) => return Err(GlangError::runtime(format!("Cannot repeat a string {} times", count))),
+                ref other => return Err(GlangError::runtime(format!("Builtin {:?} expects an integer count, got {}", name, other.type_name())).with_code("E0302"))
+            }
+        },
+        _ => return Ok(None)
+    };
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, arguments: &[eval::RuntimeVal]) -> Result<eval::RuntimeVal, GlangError> {
+        Ok(call_builtin(name, arguments, environment::new_env(None))?.expect("builtin exists"))
+    }
+
+    #[test]
+    fn repeat_builds_the_string() {
+        assert_eq!(call("repeat", &["ab".into(), 3.into()]).unwrap().to_string(), "ababab");
+        assert_eq!(call("repeat", &["ab".into(), 0.into()]).unwrap().to_string(), "");
+    }
+
+    #[test]
+    fn repeat_rejects_huge_results_instead_of_allocating() {
+        assert!(call("repeat", &["ab".into(), i64::MAX.into()]).is_err());
+        assert!(call("repeat", &["x".into(), 3_000_000_000i64.into()]).is_err());
+        assert!(call("repeat", &["x".into(), (MAX_REPEAT_LENGTH as i64).into()]).is_ok());
+        assert!(call("repeat", &["x".into(), (MAX_REPEAT_LENGTH as i64 + 1).into()]).is_err());
+    }
+
+    #[test]
+    fn format_value_pads_and_aligns() {
+        assert_eq!(format_value(&42.into(), "5").unwrap(), "   42");
+        assert_eq!(format_value(&"ab".into(), "5").unwrap(), "ab   ");
+        assert_eq!(format_value(&"ab".into(), "*^6").unwrap(), "**ab**");
+        assert_eq!(format_value(&42.into(), "<4").unwrap(), "42  ");
+        assert_eq!(format_value(&1.23456.into(), ">8.2").unwrap(), "    1.23");
+        assert_eq!(format_value(&7.into(), ".1").unwrap(), "7.0");
+        assert_eq!(format_value(&"glang".into(), ".2").unwrap(), "gl");
+    }
+
+    #[test]
+    fn format_value_rejects_bad_specifiers() {
+        assert_eq!(format_value(&1.into(), "x").unwrap_err().code, "E0300");
+        assert_eq!(format_value(&1.into(), "5.x").unwrap_err().code, "E0300");
+        assert_eq!(format_value(&true.into(), ".2").unwrap_err().code, "E0302");
+    }
+
+    #[test]
+    fn format_value_caps_width_and_precision() {
+        assert_eq!(format_value(&1.into(), "1000000000000").unwrap_err().code, "E0300");
+        assert_eq!(format_value(&1.5.into(), ".1000000000000").unwrap_err().code, "E0300");
+        assert_eq!(format_value(&1.into(), &MAX_FORMAT_WIDTH.to_string()).unwrap().len(), MAX_FORMAT_WIDTH);
+    }
+
+    #[test]
+    fn format_template_fills_placeholders_in_order() {
+        assert_eq!(format_template("{} + {} = {:0>3}", &[1.into(), 2.into(), 3.into()]).unwrap(), "1 + 2 = 003");
+        assert_eq!(format_template("{{}}", &[]).unwrap(), "{}");
+        assert!(format_template("{} {}", &[1.into()]).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use super::error::GlangError;
+use std::env;
+use std::io::IsTerminal;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+struct Palette {
+    colour: bool
+}
+impl Palette {
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.colour {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+pub fn colour_enabled() -> bool {
+    // Colour is only used on a terminal, and can be turned off with NO_COLOR
+    std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+// Renders an error in the style of rustc, with the offending source line and the span underlined, e.g.
+//
+// Runtime error[E0303]: Division by zero
+//  --> script.glang:2:9
+//   |
+// 2 | let y = x / 0
+//   |         ^^^^^
+pub fn render(error: &GlangError, source: &str, file_name: &str, colour: bool) -> String {
+    let palette = Palette { colour };
+    let header = format!("{}[{}]", error.kind, error.code);
+    let mut output = format!("{}{}\n", palette.paint(RED, &header), palette.paint(BOLD, &format!(": {}", error.message)));
+
+    let span = match error.span {
+        Some(span) => span,
+        None => {
+            output.push_str(&format!("{} {}\n", palette.paint(BLUE, "-->"), file_name));
+            for note in error.help.iter() {
+                output.push_str(&format!("{} {}\n", palette.paint(BLUE, "="), palette.paint(BOLD, &format!("help: {}", note))));
+            }
+            return output
+        }
+    };
+
+    let line_number = span.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+    let line_text = source.lines().nth(span.line - 1).unwrap_or("");
+
+    // Only the first line of a span is underlined, with at least one caret so that empty spans are still visible
+    let spanned_text = source.get(span.offset..span.offset + span.length).unwrap_or("");
+    let underline_length = spanned_text.chars().take_while(|character| *character != '\n').count().max(1);
+    // Tabs are kept so that the carets line up with the source line
+    let padding: String = line_text.chars().take(span.column - 1).map(|character| if character == '\t' { '\t' } else { ' ' }).collect();
+
+    output.push_str(&format!("{}{} {}:{}:{}\n", gutter, palette.paint(BLUE, "-->"), file_name, span.line, span.column));
+    output.push_str(&format!("{} {}\n", gutter, palette.paint(BLUE, "|")));
+    output.push_str(&format!("{} {}\n", palette.paint(BLUE, &format!("{} |", line_number)), line_text));
+    output.push_str(&format!("{} {} {}{}\n", gutter, palette.paint(BLUE, "|"), padding, palette.paint(RED, &"^".repeat(underline_length))));
+    if !error.help.is_empty() {
+        output.push_str(&format!("{} {}\n", gutter, palette.paint(BLUE, "|")));
+    }
+    for note in error.help.iter() {
+        output.push_str(&format!("{} {} {}\n", gutter, palette.paint(BLUE, "="), palette.paint(BOLD, &format!("help: {}", note))));
+    }
+    output
+}
+
+// Returns the candidate closest to name, if any is close enough to be a likely typo
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    // Roughly one edit per three characters, so single character names never get a suggestion
+    let threshold = (name.chars().count() + 1) / 3;
+    candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn edit_distance(left: &str, right: &str) -> usize {
+    // Levenshtein distance where swapping two adjacent characters also counts as a single edit
+    let left: Vec<char> = left.chars().collect();
+    let right: Vec<char> = right.chars().collect();
+    let mut table = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=left.len() {
+        for j in 1..=right.len() {
+            let cost = if left[i - 1] == right[j - 1] { 0 } else { 1 };
+            table[i][j] = (table[i - 1][j] + 1).min(table[i][j - 1] + 1).min(table[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                table[i][j] = table[i][j].min(table[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    table[left.len()][right.len()]
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Span;
+
+    fn error_at(line: usize, column: usize, offset: usize, length: usize) -> GlangError {
+        GlangError::runtime("Division by zero".to_string()).with_code("E0303").with_span(Span { offset, length, line, column })
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        let source = "let x = 1\nlet y = x / 0\n";
+        let rendered = render(&error_at(2, 9, 18, 5), source, "script.glang", false);
+        assert_eq!(rendered, "Runtime error[E0303]: Division by zero\n --> script.glang:2:9\n  |\n2 | let y = x / 0\n  |         ^^^^^\n");
+    }
+
+    #[test]
+    fn render_keeps_tabs_and_shows_help() {
+        let source = "\tprint(x)";
+        let error = error_at(1, 8, 7, 1).with_help("did you mean `y`?".to_string());
+        let rendered = render(&error, source, "t.glang", false);
+        assert_eq!(rendered, "Runtime error[E0303]: Division by zero\n --> t.glang:1:8\n  |\n1 | \tprint(x)\n  | \t      ^\n  |\n  = help: did you mean `y`?\n");
+    }
+
+    #[test]
+    fn render_widens_the_gutter_and_handles_edge_spans() {
+        let source = format!("{}let y = 0", "\n".repeat(9));
+        let rendered = render(&error_at(10, 9, 17, 0), &source, "s", false);
+        assert!(rendered.ends_with("10 | let y = 0\n   |         ^\n"), "{}", rendered);
+        let multi_line = render(&error_at(1, 1, 0, 5), "a +\nb", "s", false);
+        assert!(multi_line.ends_with("1 | a +\n  | ^^^\n"), "{}", multi_line);
+        let without_span = render(&GlangError::syntax("Oops".to_string()).with_help("try again".to_string()), "", "s", false);
+        assert_eq!(without_span, "Syntax error[E0200]: Oops\n--> s\n= help: try again\n");
+    }
+
+    #[test]
+    fn render_colours_only_when_asked() {
+        let rendered = render(&error_at(1, 1, 0, 1), "x", "s", true);
+        assert!(rendered.starts_with(&format!("{}Runtime error[E0303]{}", RED, RESET)));
+        assert!(!render(&error_at(1, 1, 0, 1), "x", "s", false).contains('\x1b'));
+    }
+
+    #[test]
+    fn suggest_finds_likely_typos() {
+        let names = ["count", "counter", "total", "x"];
+        assert_eq!(suggest("cuont", names), Some("count".to_string()));
+        assert_eq!(suggest("countr", names), Some("count".to_string()));
+        assert_eq!(suggest("totl", names), Some("total".to_string()));
+        assert_eq!(suggest("y", names), None);
+        assert_eq!(suggest("zzzzz", names), None);
+        assert_eq!(suggest("count", names), Some("counter".to_string())); // The name itself is never suggested
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
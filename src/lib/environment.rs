@@ -1,125 +1,427 @@
 use super::eval;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
 use super::parser;
+use super::error::GlangError;
+use super::builtins;
+use super::diagnostics;
+use super::lexer;
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String, // Should be identifier
     pub value: eval::RuntimeVal   
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Function {
     pub name: String,
-    pub content: Vec<parser::Node>
+    pub parameters: Vec<String>,
+    pub content: Vec<parser::Node>,
+    pub closure: Rc<RefCell<Environment>> // Environment the function was defined in
+}
+impl fmt::Debug for Function {
+    // The captured environment is left out, it usually contains the function itself
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function").field("name", &self.name).field("parameters", &self.parameters).finish_non_exhaustive()
+    }
+}
+
+// Signature of functions provided by the host, arguments are already evaluated
+pub type NativeFn = dyn Fn(&[eval::RuntimeVal]) -> Result<eval::RuntimeVal, GlangError>;
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Option<usize>, // None accepts any number of arguments
+    pub function: Box<NativeFn>
+}
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction").field("name", &self.name).field("arity", &self.arity).finish_non_exhaustive()
+    }
+}
+
+// Where print and eprint write to. A sink set on an environment is used by every scope below it
+#[derive(Clone)]
+pub struct OutputSink(pub Rc<RefCell<dyn Write>>);
+impl fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OutputSink").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Output, // print, stdout by default
+    Error // eprint, stderr by default
+}
+
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub methods: Vec<Rc<Function>>
 }
 
 #[derive(Debug, Clone)]
 pub struct Environment {
     pub parent: Option<Rc<RefCell<Environment>>>,
     pub variables: Vec<Variable>,
-    pub functions: Vec<Function>
+    pub structs: Vec<Rc<Struct>>,
+    pub natives: Vec<Rc<NativeFunction>>,
+    pub output: Option<OutputSink>,
+    pub error_output: Option<OutputSink>
 }
 
-pub fn resolve_env(env: Rc<RefCell<Environment>>, name: &str) -> Rc<RefCell<Environment>>{
-    let env_rc = env.borrow();
-    for variable in env_rc.variables.clone() {
-        if variable.name == name {
-            return env.clone()
+pub fn new_env(parent: Option<Rc<RefCell<Environment>>>) -> Rc<RefCell<Environment>> {
+    Rc::new(RefCell::new(Environment {parent, variables: vec![], structs: vec![], natives: vec![], output: None, error_output: None}))
+}
+
+// Objects visited when looking for reference cycles through a finished scope, beyond this the scope is assumed to be in use
+const MAX_SCOPE_GRAPH_SIZE: usize = 10_000;
+const MAX_SCOPE_GRAPH_DEPTH: usize = 64;
+
+// A shared object reachable from a finished scope: a function, array, map, struct instance, struct or environment
+struct Reachable {
+    strong: usize, // Every reference to the object
+    internal: usize, // References from the scope and the other objects found, the rest come from outside
+    children: Vec<*const ()>
+}
+
+struct ScopeGraph<'a> {
+    scope: &'a Rc<RefCell<Environment>>,
+    objects: HashMap<*const (), Reachable>,
+    incomplete: bool
+}
+impl ScopeGraph<'_> {
+    // Notes one reference and gives true the first time the object is seen, when its children still need visiting
+    fn reference(&mut self, parent: *const (), key: *const (), strong: usize, depth: usize) -> bool {
+        self.objects.get_mut(&parent).expect("parents are visited first").children.push(key);
+        let first = !self.objects.contains_key(&key);
+        if first && (self.objects.len() >= MAX_SCOPE_GRAPH_SIZE || depth > MAX_SCOPE_GRAPH_DEPTH) {
+            // Left out, so everything that refers to it looks like it is still in use
+            self.incomplete = true;
+            return false
+        }
+        let object = self.objects.entry(key).or_insert(Reachable { strong, internal: 0, children: vec![] });
+        object.internal += 1;
+        first
+    }
+
+    fn visit_value(&mut self, parent: *const (), value: &eval::RuntimeVal, depth: usize) {
+        match &value.runtime_val_type {
+            eval::RuntimeValType::Function(function) => self.visit_function(parent, function, depth),
+            eval::RuntimeValType::Array(elements) => {
+                let key = Rc::as_ptr(elements) as *const ();
+                if self.reference(parent, key, Rc::strong_count(elements), depth) {
+                    for element in elements.borrow().iter() {
+                        self.visit_value(key, element, depth + 1);
+                    }
+                }
+            },
+            eval::RuntimeValType::Map(entries) => {
+                let key = Rc::as_ptr(entries) as *const ();
+                if self.reference(parent, key, Rc::strong_count(entries), depth) {
+                    for entry in entries.borrow().values() {
+                        self.visit_value(key, entry, depth + 1);
+                    }
+                }
+            },
+            eval::RuntimeValType::Struct(instance) => {
+                let key = Rc::as_ptr(instance) as *const ();
+                if self.reference(parent, key, Rc::strong_count(instance), depth) {
+                    let instance = instance.borrow();
+                    self.visit_struct(key, &instance.definition, depth + 1);
+                    for field in instance.fields.iter() {
+                        self.visit_value(key, &field.value, depth + 1);
+                    }
+                }
+            },
+            _ => {}
         }
     }
 
-    if let Some(ref parent) = env.borrow().parent {
+    fn visit_function(&mut self, parent: *const (), function: &Rc<Function>, depth: usize) {
+        let key = Rc::as_ptr(function) as *const ();
+        if self.reference(parent, key, Rc::strong_count(function), depth) {
+            self.visit_env(key, &function.closure, depth + 1);
+        }
+    }
+
+    fn visit_struct(&mut self, parent: *const (), definition: &Rc<Struct>, depth: usize) {
+        let key = Rc::as_ptr(definition) as *const ();
+        if self.reference(parent, key, Rc::strong_count(definition), depth) {
+            for method in definition.methods.iter() {
+                self.visit_function(key, method, depth + 1);
+            }
+        }
+    }
+
+    fn visit_env(&mut self, parent: *const (), env: &Rc<RefCell<Environment>>, depth: usize) {
+        // Only the scope and the scopes inside it can be part of a cycle through it, closures over anything else are skipped
+        if !is_within(env, self.scope) {
+            return
+        }
+        let key = Rc::as_ptr(env) as *const ();
+        if self.reference(parent, key, Rc::strong_count(env), depth) {
+            self.visit_scope_contents(key, env, depth + 1);
+        }
+    }
+
+    fn visit_scope_contents(&mut self, key: *const (), env: &Rc<RefCell<Environment>>, depth: usize) {
+        let env_rc = env.borrow();
+        for variable in env_rc.variables.iter() {
+            self.visit_value(key, &variable.value, depth);
+        }
+        for definition in env_rc.structs.iter() {
+            self.visit_struct(key, definition, depth);
+        }
+        if let Some(ref parent) = env_rc.parent && !Rc::ptr_eq(env, self.scope) {
+            self.visit_env(key, parent, depth);
+        }
+    }
+}
+
+fn is_within(env: &Rc<RefCell<Environment>>, scope: &Rc<RefCell<Environment>>) -> bool {
+    if Rc::ptr_eq(env, scope) {
+        return true
+    }
+    match env.borrow().parent {
+        Some(ref parent) => is_within(parent, scope),
+        None => false
+    }
+}
+
+// A function holds on to the scope it was defined in and the scope holds the function, so a scope that defines
+// a function is a reference cycle that is never freed. Called with the last handle to a scope once it has finished
+// running, this looks at everything reachable from the scope and clears it when the only references left to it
+// come from inside those cycles. Anything that escaped, such as a returned closure, is referenced from outside and keeps the scope as it is
+pub fn release_scope(env: Rc<RefCell<Environment>>) {
+    if Rc::strong_count(&env) == 1 {
+        return // Nothing captured it, so dropping the handle frees it
+    }
+    let key = Rc::as_ptr(&env) as *const ();
+    let mut graph = ScopeGraph { scope: &env, objects: HashMap::new(), incomplete: false };
+    // The handle passed in is the one outside reference the scope is expected to have
+    graph.objects.insert(key, Reachable { strong: Rc::strong_count(&env) - 1, internal: 0, children: vec![] });
+    graph.visit_scope_contents(key, &env, 0);
+    if graph.incomplete {
+        return
+    }
+
+    // Whatever is referenced from outside is in use, and so is everything it reaches
+    let mut in_use: Vec<*const ()> = graph.objects.iter().filter(|(_, object)| object.strong > object.internal).map(|(key, _)| *key).collect();
+    let mut seen: HashSet<*const ()> = in_use.iter().copied().collect();
+    while let Some(next) = in_use.pop() {
+        for child in graph.objects[&next].children.iter() {
+            if seen.insert(*child) {
+                in_use.push(*child);
+            }
+        }
+    }
+    if !seen.contains(&key) {
+        let mut env_rc = env.borrow_mut();
+        env_rc.variables.clear();
+        env_rc.structs.clear();
+    }
+}
+
+pub fn resolve_env(env: Rc<RefCell<Environment>>, name: &str) -> Result<Rc<RefCell<Environment>>, GlangError>{
+    let env_rc = env.borrow();
+    if env_rc.variables.iter().any(|variable| variable.name == name) {
+        return Ok(env.clone())
+    }
+
+    if let Some(ref parent) = env_rc.parent {
         return resolve_env(parent.clone(), name)
     } 
 
-    panic!("Cannot resolve environment")
+    Err(GlangError::runtime(format!("Variable does not exist - {:?}", name)).with_code("E0301"))
+}
+
+pub fn has_variable(env: Rc<RefCell<Environment>>, name: &str) -> bool{
+    let env_rc = env.borrow();
+    if env_rc.variables.iter().any(|variable| variable.name == name) {
+        return true
+    }
+
+    match env_rc.parent {
+        Some(ref parent) => has_variable(parent.clone(), name),
+        None => false
+    }
+}
+
+fn visible_names(env: Rc<RefCell<Environment>>) -> Vec<String>{
+    let env_rc = env.borrow();
+    let mut names: Vec<String> = env_rc.variables.iter().map(|variable| variable.name.clone()).collect();
+    if let Some(ref parent) = env_rc.parent {
+        names.extend(visible_names(parent.clone()));
+    }
+    names
+}
+
+fn suggest_name(error: GlangError, env: Rc<RefCell<Environment>>, name: &str) -> GlangError{
+    // Typos are matched against every name in scope, the builtins and the keywords
+    let names = visible_names(env);
+    let candidates = names.iter().map(String::as_str).chain(builtins::BUILTINS).chain(lexer::KEYWORDS);
+    match diagnostics::suggest(name, candidates) {
+        Some(suggestion) => error.with_help(format!("did you mean `{}`?", suggestion)),
+        None => error
+    }
 }
 
-pub fn declare_variable (env: Rc<RefCell<Environment>>, identifier: &str, value: &eval::RuntimeVal) -> eval::RuntimeVal{
+pub fn declare_variable (env: Rc<RefCell<Environment>>, identifier: &str, value: &eval::RuntimeVal) -> Result<eval::RuntimeVal, GlangError>{
     let mut env_rc = env.borrow_mut();
-    for variable in env_rc.variables.clone() {
-        if variable.name == identifier {
-            panic!("Variable already defined: {:?}", variable.name)
-        } else {
-            env_rc.variables.push(Variable { name: identifier.to_string(), value: value.clone() });
-            return value.clone()
-        }
+    if env_rc.variables.iter().any(|variable| variable.name == identifier) {
+        return Err(GlangError::runtime(format!("Variable already defined: {:?}", identifier)).with_code("E0306"))
     }
     env_rc.variables.push(Variable { name: identifier.to_string(), value: value.clone() });
-    value.clone()
+    Ok(value.clone())
 }
 
-pub fn assign_variable (env: Rc<RefCell<Environment>>, identifier: &str, value: &eval::RuntimeVal) -> eval::RuntimeVal{
-    // identifier.value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string()
-    let env_r = resolve_env(env, identifier);
+pub fn assign_variable (env: Rc<RefCell<Environment>>, identifier: &str, value: &eval::RuntimeVal) -> Result<eval::RuntimeVal, GlangError>{
+    let env_r = resolve_env(env.clone(), identifier).map_err(|_| {
+        let error = GlangError::runtime(format!("Cannot assign uninitialised variable - {:?}", identifier)).with_code("E0301");
+        suggest_name(error, env, identifier).with_help(format!("declare it first with `let {} = ...`", identifier))
+    })?;
     let mut env_rc = env_r.borrow_mut();
-    for (count, variable) in env_rc.variables.clone().iter().enumerate() {
+    for variable in env_rc.variables.iter_mut() {
         if identifier == variable.name {
-            env_rc.variables[count].value = value.clone();
-            return eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Null }
+            variable.value = value.clone();
+            return Ok(eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Null })
         }
     }
-    // println!("{:?}",env);
-    panic!("Cannot assign uninitialised variable - {:?}", identifier)
+    Err(GlangError::runtime(format!("Cannot assign uninitialised variable - {:?}", identifier)).with_code("E0301"))
 }
 
-pub fn lookup_variable (env: Rc<RefCell<Environment>>, identifier: &str) -> eval::RuntimeVal {
-    let env_r = resolve_env(env, identifier);
-    let mut env_rc = env_r.borrow_mut();
-    for variable in env_rc.variables.clone() {
+pub fn lookup_variable (env: Rc<RefCell<Environment>>, identifier: &str) -> Result<eval::RuntimeVal, GlangError> {
+    let env_r = resolve_env(env.clone(), identifier).map_err(|error| suggest_name(error, env, identifier))?;
+    let env_rc = env_r.borrow();
+    for variable in env_rc.variables.iter() {
         if identifier == variable.name {
-            return variable.value
+            return Ok(variable.value.clone())
         }
     }
-    panic!("Variable does not exist - {:?}", identifier)
+    Err(GlangError::runtime(format!("Variable does not exist - {:?}", identifier)).with_code("E0301"))
+}
+
+
+pub fn declare_function (env: Rc<RefCell<Environment>>, identifier: &str, parameters: &[String], content: &[parser::Node]) -> Result<eval::RuntimeVal, GlangError>{
+    let function = Function {
+        name: identifier.to_string(),
+        parameters: parameters.to_vec(),
+        content: content.to_vec(),
+        closure: env.clone()
+    };
+    declare_variable(env, identifier, &eval::RuntimeVal { runtime_val_type: eval::RuntimeValType::Function(Rc::new(function)) })?;
+    Ok(eval::RuntimeVal {
+        runtime_val_type: eval::RuntimeValType::Null
+    })
+}
+
+pub fn lookup_function (env: Rc<RefCell<Environment>>, identifier: &str) -> Result<Rc<Function>, GlangError> {
+    match lookup_variable(env, identifier)?.runtime_val_type {
+        eval::RuntimeValType::Function(function) => Ok(function),
+        other => Err(GlangError::runtime(format!("{:?} is not a function, got {}", identifier, other.type_name())).with_code("E0302"))
+    }
 }
 
 
-pub fn resolve_env_func(env: Rc<RefCell<Environment>>, name: &str) -> Rc<RefCell<Environment>>{
+pub fn resolve_env_struct(env: Rc<RefCell<Environment>>, name: &str) -> Result<Rc<RefCell<Environment>>, GlangError>{
     let env_rc = env.borrow();
-    for function in env_rc.functions.clone() {
-        if function.name == name {
-            return env.clone()
-        }
+    if env_rc.structs.iter().any(|struct_| struct_.name == name) {
+        return Ok(env.clone())
     }
 
-    if let Some(ref parent) = env.borrow().parent {
-        return resolve_env_func(parent.clone(), name)
+    if let Some(ref parent) = env_rc.parent {
+        return resolve_env_struct(parent.clone(), name)
     } 
 
-    panic!("Cannot resolve environment")
+    Err(GlangError::runtime(format!("Struct does not exist - {:?}", name)).with_code("E0301"))
 }
 
-pub fn declare_function (env: Rc<RefCell<Environment>>, identifier: &str, content: &Vec<parser::Node>) -> eval::RuntimeVal{
+pub fn declare_struct (env: Rc<RefCell<Environment>>, struct_: Struct) -> Result<eval::RuntimeVal, GlangError>{
     let mut env_rc = env.borrow_mut();
-    for variable in env_rc.variables.clone() {
-        if variable.name == identifier {
-            panic!("Variable already defined: {:?}", variable.name)
-        } else {
-            env_rc.functions.push(Function { name: identifier.to_string(), content: content.clone() });
-            return eval::RuntimeVal {
-                runtime_val_type: eval::RuntimeValType::Null
-            }
-        }
+    if env_rc.structs.iter().any(|existing| existing.name == struct_.name) {
+        return Err(GlangError::runtime(format!("Struct already defined: {:?}", struct_.name)).with_code("E0306"))
     }
-    env_rc.functions.push(Function { name: identifier.to_string(), content: content.clone() });
-    return eval::RuntimeVal {
+    env_rc.structs.push(Rc::new(struct_));
+    Ok(eval::RuntimeVal {
         runtime_val_type: eval::RuntimeValType::Null
+    })
+}
+
+fn visible_struct_names(env: Rc<RefCell<Environment>>) -> Vec<String>{
+    let env_rc = env.borrow();
+    let mut names: Vec<String> = env_rc.structs.iter().map(|struct_| struct_.name.clone()).collect();
+    if let Some(ref parent) = env_rc.parent {
+        names.extend(visible_struct_names(parent.clone()));
     }
+    names
 }
 
-pub fn lookup_function (env: Rc<RefCell<Environment>>, identifier: &str) -> parser::Node {
-    let env_r = resolve_env_func(env, identifier);
-    let mut env_rc = env_r.borrow_mut();
-    for function in env_rc.functions.clone() {
-        if identifier == function.name {
-            return parser::Node {
-                node_type: parser::NodeType::Scope,
-                value: None, 
-                body: function.content
-            }
+pub fn lookup_struct (env: Rc<RefCell<Environment>>, identifier: &str) -> Result<Rc<Struct>, GlangError> {
+    let env_r = resolve_env_struct(env.clone(), identifier).map_err(|error| {
+        let names = visible_struct_names(env);
+        match diagnostics::suggest(identifier, names.iter().map(String::as_str)) {
+            Some(suggestion) => error.with_help(format!("did you mean `{}`?", suggestion)),
+            None => error
+        }
+    })?;
+    let env_rc = env_r.borrow();
+    for struct_ in env_rc.structs.iter() {
+        if identifier == struct_.name {
+            return Ok(struct_.clone())
         }
     }
-    panic!("Function does not exist - {:?}", identifier)
+    Err(GlangError::runtime(format!("Struct does not exist - {:?}", identifier)).with_code("E0301"))
+}
+
+
+pub fn declare_native (env: Rc<RefCell<Environment>>, native: NativeFunction) {
+    // Registering a name again replaces the earlier native function
+    let mut env_rc = env.borrow_mut();
+    env_rc.natives.retain(|existing| existing.name != native.name);
+    env_rc.natives.push(Rc::new(native));
+}
+
+pub fn lookup_native (env: Rc<RefCell<Environment>>, identifier: &str) -> Option<Rc<NativeFunction>> {
+    let env_rc = env.borrow();
+    if let Some(native) = env_rc.natives.iter().find(|native| native.name == identifier) {
+        return Some(native.clone())
+    }
+
+    match env_rc.parent {
+        Some(ref parent) => lookup_native(parent.clone(), identifier),
+        None => None
+    }
+}
+
+
+fn resolve_sink (env: Rc<RefCell<Environment>>, stream: Stream) -> Option<OutputSink> {
+    let env_rc = env.borrow();
+    let sink = match stream {
+        Stream::Output => &env_rc.output,
+        Stream::Error => &env_rc.error_output
+    };
+    if sink.is_some() {
+        return sink.clone()
+    }
+
+    match env_rc.parent {
+        Some(ref parent) => resolve_sink(parent.clone(), stream),
+        None => None
+    }
+}
+
+pub fn write_output (env: Rc<RefCell<Environment>>, stream: Stream, text: &str) -> Result<(), GlangError> {
+    // Without a sink set by the host output goes to the process's stdout or stderr
+    let result = match (resolve_sink(env, stream), stream) {
+        (Some(sink), _) => sink.0.borrow_mut().write_all(text.as_bytes()),
+        (None, Stream::Output) => io::stdout().write_all(text.as_bytes()),
+        (None, Stream::Error) => io::stderr().write_all(text.as_bytes())
+    };
+    result.map_err(|error| GlangError::runtime(format!("Could not write output - {}", error)))
 }
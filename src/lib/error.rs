@@ -0,0 +1,64 @@
+use std::fmt;
+use super::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Lexical, // Raised while tokenising
+    Syntax, // Raised while generating the AST
+    Runtime // Raised while evaluating
+}
+
+#[derive(Debug, Clone)]
+pub struct GlangError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub code: &'static str, // Listed in the readme, the hundreds digit matches the kind
+    pub span: Option<Span>, // Where in the source the error was raised, if known
+    pub help: Vec<String> // Notes shown under the source snippet
+}
+impl GlangError {
+    pub fn lexical(message: String) -> GlangError {
+        GlangError { kind: ErrorKind::Lexical, message, code: "E0100", span: None, help: vec![] }
+    }
+
+    pub fn syntax(message: String) -> GlangError {
+        GlangError { kind: ErrorKind::Syntax, message, code: "E0200", span: None, help: vec![] }
+    }
+
+    pub fn runtime(message: String) -> GlangError {
+        GlangError { kind: ErrorKind::Runtime, message, code: "E0300", span: None, help: vec![] }
+    }
+
+    pub fn with_span(mut self, span: Span) -> GlangError {
+        // Only the first known span is kept, so an error points at the innermost node that raised it
+        if self.span.is_none() && span != Span::default() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> GlangError {
+        self.code = code;
+        self
+    }
+
+    pub fn with_help(mut self, note: String) -> GlangError {
+        self.help.push(note);
+        self
+    }
+}
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Lexical => write!(f, "Lexical error"),
+            ErrorKind::Syntax => write!(f, "Syntax error"),
+            ErrorKind::Runtime => write!(f, "Runtime error")
+        }
+    }
+}
+impl fmt::Display for GlangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+impl std::error::Error for GlangError {}
\ No newline at end of file
@@ -1,21 +1,31 @@
-use std::cell::Ref;
-use std::env;
-
 use super::lexer;
 use super::parser;
 use super::environment;
+use super::builtins;
+use super::error::GlangError;
+use super::diagnostics;
+use super::userdata::{HostObject, UserData};
+use super::bigint::BigInt;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum RuntimeValType {
     Null,
     NumericInteger(i64),
+    BigInteger(BigInt), // Only holds integers outside the i64 range, smaller results are demoted to NumericInteger
     NumericFloat(f64),
     StringLiteral(String),
     Boolean(bool),
     Break,
-    Runtime
+    Return(Box<RuntimeVal>),
+    Function(Rc<environment::Function>),
+    Array(Rc<RefCell<Vec<RuntimeVal>>>),
+    Map(Rc<RefCell<BTreeMap<MapKey, RuntimeVal>>>),
+    Struct(Rc<RefCell<StructInstance>>),
+    UserData(UserData)
 }
 impl RuntimeValType {
     pub fn extract_int_value(&self) -> Option<&i64> {
@@ -50,6 +60,39 @@ impl RuntimeValType {
         }
     }
 
+    pub fn extract_array_value(&self) -> Option<&Rc<RefCell<Vec<RuntimeVal>>>> {
+        if let RuntimeValType::Array(elements) = self {
+            Some(elements)
+        } else {
+            None
+        }
+    }
+
+    pub fn extract_map_value(&self) -> Option<&Rc<RefCell<BTreeMap<MapKey, RuntimeVal>>>> {
+        if let RuntimeValType::Map(entries) = self {
+            Some(entries)
+        } else {
+            None
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RuntimeValType::Null => "null",
+            RuntimeValType::NumericInteger(_) | RuntimeValType::BigInteger(_) => "integer",
+            RuntimeValType::NumericFloat(_) => "float",
+            RuntimeValType::StringLiteral(_) => "string",
+            RuntimeValType::Boolean(_) => "boolean",
+            RuntimeValType::Break => "break",
+            RuntimeValType::Return(_) => "return",
+            RuntimeValType::Function(_) => "function",
+            RuntimeValType::Array(_) => "array",
+            RuntimeValType::Map(_) => "map",
+            RuntimeValType::Struct(_) => "struct",
+            RuntimeValType::UserData(_) => "userdata"
+        }
+    }
+
 }
 
 #[derive(Debug, Clone)]
@@ -57,816 +100,1056 @@ pub struct RuntimeVal {
     pub runtime_val_type : RuntimeValType,
 }
 
-pub fn eval(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal {
-    match node.node_type {
-        parser::NodeType::Break => {
-            RuntimeVal {
-                runtime_val_type: RuntimeValType::Break
-            }
-        },
-        parser::NodeType::NumericLiteral => {
-            let token = &node.value.as_ref().unwrap();
-
-            match token.token_type {
-                lexer::TokenType::Float(_) => {
-                    let token_value = token.token_type.extract_float_value().unwrap();
-                    RuntimeVal {
-                        runtime_val_type: RuntimeValType::NumericFloat(*token_value),
-                    }
-                },
-                lexer::TokenType::Integer(_) => {
-                    let token_value = token.token_type.extract_int_value().unwrap();
-                    RuntimeVal {
-                        runtime_val_type: RuntimeValType::NumericInteger(*token_value),
-                    }
-                },
-                _=>panic!()
+impl RuntimeVal {
+    pub fn null() -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::Null }
+    }
 
+    // Used by native functions to check their arguments, failing with a type error
+    pub fn expect_int(&self) -> Result<i64, GlangError> {
+        match self.runtime_val_type {
+            RuntimeValType::NumericInteger(int) => Ok(int),
+            RuntimeValType::BigInteger(ref big) => Err(GlangError::runtime(format!("Expected an integer that fits in 64 bits, got {}", big)).with_code("E0308")),
+            ref other => Err(GlangError::runtime(format!("Expected an integer, got {}", other.type_name())).with_code("E0302"))
+        }
+    }
+
+    pub fn expect_float(&self) -> Result<f64, GlangError> {
+        // Integers are accepted wherever a float is expected
+        match self.runtime_val_type {
+            RuntimeValType::NumericFloat(float) => Ok(float),
+            RuntimeValType::NumericInteger(int) => Ok(int as f64),
+            RuntimeValType::BigInteger(ref big) => Ok(big.to_f64()),
+            ref other => Err(GlangError::runtime(format!("Expected a float, got {}", other.type_name())).with_code("E0302"))
+        }
+    }
+
+    pub fn expect_bool(&self) -> Result<bool, GlangError> {
+        match self.runtime_val_type {
+            RuntimeValType::Boolean(boolean) => Ok(boolean),
+            ref other => Err(GlangError::runtime(format!("Expected a boolean, got {}", other.type_name())).with_code("E0302"))
+        }
+    }
+
+    pub fn expect_str(&self) -> Result<&str, GlangError> {
+        match &self.runtime_val_type {
+            RuntimeValType::StringLiteral(string_) => Ok(string_),
+            other => Err(GlangError::runtime(format!("Expected a string, got {}", other.type_name())).with_code("E0302"))
+        }
+    }
+
+    // Gives back the host object behind a userdata value, failing if it holds another type
+    pub fn expect_userdata<T: HostObject>(&self) -> Result<&T, GlangError> {
+        match &self.runtime_val_type {
+            RuntimeValType::UserData(userdata) => match userdata.downcast_ref::<T>() {
+                Some(object) => Ok(object),
+                None => Err(GlangError::runtime(format!("Expected a different userdata type, got {}", userdata.type_name)).with_code("E0302"))
+            },
+            other => Err(GlangError::runtime(format!("Expected userdata, got {}", other.type_name())).with_code("E0302"))
+        }
+    }
+
+    pub fn userdata<T: HostObject>(object: T) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::UserData(UserData::new(object)) }
+    }
+
+    // Source like representation used when echoing values, strings are quoted
+    pub fn repr(&self) -> String {
+        self.write_repr(&mut vec![])
+    }
+
+    fn write_repr(&self, containers: &mut Vec<*const ()>) -> String {
+        // Containers being written are tracked so that one holding itself prints as [...] instead of recursing forever
+        let pointer = match &self.runtime_val_type {
+            RuntimeValType::Array(elements) => Some(Rc::as_ptr(elements) as *const ()),
+            RuntimeValType::Map(entries) => Some(Rc::as_ptr(entries) as *const ()),
+            RuntimeValType::Struct(instance) => Some(Rc::as_ptr(instance) as *const ()),
+            _ => None
+        };
+        if let Some(pointer) = pointer {
+            if containers.contains(&pointer) {
+                return match self.runtime_val_type {
+                    RuntimeValType::Array(_) => "[...]".to_string(),
+                    _ => "{...}".to_string()
+                }
             }
-        },
-        parser::NodeType::BinaryExpr(_) => {
-            eval_binary_expr(node, env)
-        },
-        parser::NodeType::UnaryExpr(_) => {
-            eval_unary_expr(node, env)
-        },
-        parser::NodeType::Identifier => {
-            eval_identifier(node, env)
-        },
-        parser::NodeType::Assignment => {
-            let mut rhs: parser::Node;
-            eval_assignment(node, env)
-        },
-        parser::NodeType::Declaration => {
-            eval_declaration(node, env)
-        },
-        parser::NodeType::StringLiteral => {
-            let token = &node.value.as_ref().unwrap();
-            let token_value = token.token_type.extract_str_value().unwrap();
-            RuntimeVal {
-                runtime_val_type: RuntimeValType::StringLiteral(token_value.to_string())
-            }
-        },
-        parser::NodeType::Boolean => {
-            let token = &node.value.as_ref().unwrap();
-            let token_value = token.token_type.extract_bool_value().unwrap();
-            RuntimeVal {
-                runtime_val_type: RuntimeValType::Boolean(*token_value)
-            }
-        },
-        parser::NodeType::EOL => {
-            panic!("This is impossible to reach")
-        },
-        parser::NodeType::Scope => {
-            eval(node, env)
+            containers.push(pointer);
         }
-        _ => {
-            panic!()
+
+        let text = match &self.runtime_val_type {
+            RuntimeValType::Null => "null".to_string(),
+            RuntimeValType::NumericInteger(int) => int.to_string(),
+            RuntimeValType::BigInteger(big) => big.to_string(),
+            RuntimeValType::NumericFloat(float) => format!("{:?}", float),
+            RuntimeValType::StringLiteral(string_) => format!("{:?}", string_),
+            RuntimeValType::Boolean(boolean) => boolean.to_string(),
+            RuntimeValType::Break => "break".to_string(),
+            RuntimeValType::Return(value) => value.write_repr(containers),
+            RuntimeValType::Function(function) => format!("<func {}>", function.name),
+            RuntimeValType::Array(elements) => {
+                let elements: Vec<String> = elements.borrow().iter().map(|element| element.write_repr(containers)).collect();
+                format!("[{}]", elements.join(", "))
+            },
+            RuntimeValType::Map(entries) => {
+                let entries: Vec<String> = entries.borrow().iter().map(|(key, value)| format!("{}: {}", key.to_value().repr(), value.write_repr(containers))).collect();
+                format!("{{{}}}", entries.join(", "))
+            },
+            RuntimeValType::Struct(instance) => {
+                let instance = instance.borrow();
+                let fields: Vec<String> = instance.fields.iter().map(|field| format!("{}: {}", field.name, field.value.write_repr(containers))).collect();
+                format!("{} {{{}}}", instance.definition.name, fields.join(", "))
+            },
+            RuntimeValType::UserData(userdata) => userdata.host().display()
+        };
+        if pointer.is_some() {
+            containers.pop();
+        }
+        text
+    }
+}
+
+// What print shows: strings are written as they are, everything else like repr so nested strings stay quoted
+impl fmt::Display for RuntimeVal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.runtime_val_type {
+            RuntimeValType::StringLiteral(string_) => f.write_str(string_),
+            _ => f.write_str(&self.repr())
+        }
+    }
+}
+
+// Conversions for hosts passing values into scripts
+impl From<i64> for RuntimeVal {
+    fn from(int: i64) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::NumericInteger(int) }
+    }
+}
+impl From<f64> for RuntimeVal {
+    fn from(float: f64) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::NumericFloat(float) }
+    }
+}
+impl From<bool> for RuntimeVal {
+    fn from(boolean: bool) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::Boolean(boolean) }
+    }
+}
+impl From<&str> for RuntimeVal {
+    fn from(string_: &str) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::StringLiteral(string_.to_string()) }
+    }
+}
+impl From<String> for RuntimeVal {
+    fn from(string_: String) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::StringLiteral(string_) }
+    }
+}
+impl From<Vec<RuntimeVal>> for RuntimeVal {
+    fn from(elements: Vec<RuntimeVal>) -> RuntimeVal {
+        RuntimeVal { runtime_val_type: RuntimeValType::Array(Rc::new(RefCell::new(elements))) }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructInstance {
+    pub definition: Rc<environment::Struct>,
+    pub fields: Vec<environment::Variable>
+}
+
+// Maps are ordered by key so that iterating over them is deterministic
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MapKey {
+    Boolean(bool),
+    NumericInteger(i64),
+    StringLiteral(String)
+}
+impl MapKey {
+    pub fn from_value(value: &RuntimeVal) -> Result<MapKey, GlangError> {
+        match &value.runtime_val_type {
+            RuntimeValType::Boolean(boolean) => Ok(MapKey::Boolean(*boolean)),
+            RuntimeValType::NumericInteger(int) => Ok(MapKey::NumericInteger(*int)),
+            RuntimeValType::StringLiteral(string_) => Ok(MapKey::StringLiteral(string_.clone())),
+            RuntimeValType::BigInteger(big) => Err(GlangError::runtime(format!("Integer map keys must fit in 64 bits, got {}", big)).with_code("E0308")),
+            other => Err(GlangError::runtime(format!("Map keys must be strings, integers or booleans, got {}", other.type_name())).with_code("E0302"))
         }
     }
+
+    pub fn to_value(&self) -> RuntimeVal {
+        let runtime_val_type = match self {
+            MapKey::Boolean(boolean) => RuntimeValType::Boolean(*boolean),
+            MapKey::NumericInteger(int) => RuntimeValType::NumericInteger(*int),
+            MapKey::StringLiteral(string_) => RuntimeValType::StringLiteral(string_.clone())
+        };
+        RuntimeVal { runtime_val_type }
+    }
+}
+
+fn type_error(left: &RuntimeVal, right: &RuntimeVal, operator: &str) -> GlangError {
+    GlangError::runtime(format!("Unsupported operand types for {}: {} and {}", operator, left.runtime_val_type.type_name(), right.runtime_val_type.type_name())).with_code("E0302")
+}
+
+fn eval_condition(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<bool, GlangError> {
+    let condition = eval(node, env)?;
+    match condition.runtime_val_type {
+        RuntimeValType::Boolean(boolean) => Ok(boolean),
+        other => Err(GlangError::runtime(format!("Condition must be a boolean, got {}", other.type_name())).with_code("E0302").with_span(node.span))
+    }
+}
+
+pub fn eval(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    // Errors without a location take the span of the innermost node that raised them
+    eval_node(node, env).map_err(|error| error.with_span(node.span))
 }
 
-pub fn eval_identifier(identifier: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal{
+fn eval_node(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    // Only dispatches, every node type is evaluated in its own function so this frame stays small,
+    // it sits on the stack once for every level of nesting in a script
+    match node.node_type {
+        parser::NodeType::Break => Ok(RuntimeVal { runtime_val_type: RuntimeValType::Break }),
+        parser::NodeType::EOL => Ok(RuntimeVal { runtime_val_type: RuntimeValType::Null }),
+        parser::NodeType::NumericLiteral => eval_numeric_literal(node),
+        parser::NodeType::StringLiteral => eval_string_literal(node),
+        parser::NodeType::InterpolatedString => eval_interpolated_string(node, env),
+        parser::NodeType::Boolean => eval_boolean(node),
+        parser::NodeType::BinaryExpr(_) => eval_binary_expr(node, env),
+        parser::NodeType::UnaryExpr(_) => eval_unary_expr(node, env),
+        parser::NodeType::Identifier => eval_identifier(node, env),
+        parser::NodeType::Assignment => eval_assignment(node, env),
+        parser::NodeType::Declaration => eval_declaration(node, env),
+        parser::NodeType::Scope => eval_block(node, env),
+        parser::NodeType::FunctionCall(_) => eval_function_call(node, env),
+        parser::NodeType::Call => eval_call(node, env),
+        parser::NodeType::Array => eval_array(node, env),
+        parser::NodeType::Map => eval_map(node, env),
+        parser::NodeType::Index => eval_index(node, env),
+        parser::NodeType::IndexAssignment => eval_index_assignment(node, env),
+        parser::NodeType::StructInstance => eval_struct_instance(node, env),
+        parser::NodeType::FieldAccess => eval_field_access(node, env),
+        parser::NodeType::FieldAssignment => eval_field_assignment(node, env),
+        parser::NodeType::MethodCall => eval_method_call(node, env),
+        parser::NodeType::CompoundAssignment(_) => eval_compound_assignment(node, env),
+        parser::NodeType::AnonymousFunction => Ok(eval_anonymous_function(node, env)),
+        parser::NodeType::Return => eval_return(node, env),
+        _ => Err(GlangError::runtime(format!("Cannot evaluate {:?} as an expression", node.node_type)))
+    }
+}
+
+fn eval_numeric_literal(node: &parser::Node) -> Result<RuntimeVal, GlangError> {
+    let token = node.value.as_ref().unwrap();
+    let runtime_val_type = match token.token_type {
+        lexer::TokenType::Float(value) => RuntimeValType::NumericFloat(value),
+        lexer::TokenType::Integer(value) => RuntimeValType::NumericInteger(value),
+        lexer::TokenType::BigInteger(ref big) => RuntimeValType::BigInteger(big.clone()),
+        _ => return Err(GlangError::runtime(format!("Invalid numeric literal - {:?}", token.token_type)))
+    };
+    Ok(RuntimeVal { runtime_val_type })
+}
+
+fn eval_string_literal(node: &parser::Node) -> Result<RuntimeVal, GlangError> {
+    let token_value = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::StringLiteral(token_value.to_string()) })
+}
+
+fn eval_interpolated_string(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    // Embedded values are formatted the same way print shows them
+    let mut text = String::new();
+    for part in node.body.iter() {
+        text.push_str(&eval(part, env.clone())?.to_string());
+    }
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::StringLiteral(text) })
+}
+
+fn eval_boolean(node: &parser::Node) -> Result<RuntimeVal, GlangError> {
+    let token_value = node.value.as_ref().unwrap().token_type.extract_bool_value().unwrap();
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Boolean(*token_value) })
+}
+
+fn eval_array(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    let elements = node.body.iter().map(|element| eval(element, env.clone())).collect::<Result<Vec<RuntimeVal>, GlangError>>()?;
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Array(Rc::new(RefCell::new(elements))) })
+}
+
+fn eval_map(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    let mut entries: BTreeMap<MapKey, RuntimeVal> = BTreeMap::new();
+    for pair in node.body.chunks(2) {
+        let key = eval(&pair[0], env.clone())?;
+        let value = eval(&pair[1], env.clone())?;
+        entries.insert(MapKey::from_value(&key)?, value);
+    }
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Map(Rc::new(RefCell::new(entries))) })
+}
+
+fn eval_anonymous_function(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal {
+    let (parameters, content) = function_definition_parts(node);
+    let function = environment::Function { name: "anonymous".to_string(), parameters, content: content.to_vec(), closure: env };
+    RuntimeVal { runtime_val_type: RuntimeValType::Function(Rc::new(function)) }
+}
+
+pub fn eval_identifier(identifier: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
     let identifier_string = identifier.value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string();
     environment::lookup_variable(env, &identifier_string)
 }
 
-pub fn eval_assignment(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal{
+pub fn eval_assignment(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
     let identifier_string = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string();
-    let eval_rhs = eval(&node.body[0], env.clone());
+    let eval_rhs = eval(&node.body[0], env.clone())?;
     environment::assign_variable(env, &identifier_string, &eval_rhs)
 }
 
-pub fn eval_declaration(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal{
+pub fn eval_declaration(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
     let identifier_string = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string();
-    let eval_rhs = eval(&node.body[0], env.clone());
+    let eval_rhs = eval(&node.body[0], env.clone())?;
     environment::declare_variable(env, &identifier_string, &eval_rhs)
 }
 
-fn eval_numeric_unary_expr(right: &RuntimeVal, operator: &str) -> RuntimeVal {
-    match operator {
-        "!" => {
-            let right_type = &right.runtime_val_type;
-            if matches!(right_type, RuntimeValType::NumericInteger(_)){
-                let right_value = right_type.extract_int_value().unwrap();
-                return RuntimeVal {
-                    runtime_val_type: RuntimeValType::NumericInteger(!*right_value)
-                }
-            }
-            panic!("Incorrect type for ! operator")
+fn function_definition_parts(node: &parser::Node) -> (Vec<String>, &[parser::Node]) {
+    // Function nodes hold their parameter identifiers first and their scope last
+    let (scope, parameter_nodes) = node.body.split_last().unwrap();
+    let parameters: Vec<String> = parameter_nodes.iter().map(|parameter| parameter.value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string()).collect();
+    (parameters, &scope.body)
+}
 
-        },
-        _ => {
-            panic!()
+fn eval_arguments(nodes: &[parser::Node], env: Rc<RefCell<environment::Environment>>) -> Result<Vec<RuntimeVal>, GlangError> {
+    nodes.iter().map(|argument| eval(argument, env.clone())).collect()
+}
+
+// Deeper recursion is an error rather than overflowing the host's stack. The default leaves room on
+// an 8 MiB thread even in a debug build, hosts with a bigger stack can raise it with set_max_call_depth
+pub const MAX_CALL_DEPTH: usize = 200;
+
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static CALL_DEPTH_LIMIT: Cell<usize> = const { Cell::new(MAX_CALL_DEPTH) };
+}
+
+// The limit belongs to the thread rather than to one interpreter, since it is the thread's stack that runs out
+pub fn set_max_call_depth(depth: usize) {
+    CALL_DEPTH_LIMIT.with(|limit| limit.set(depth));
+}
+
+pub fn max_call_depth() -> usize {
+    CALL_DEPTH_LIMIT.with(Cell::get)
+}
+
+// Counts a function call for as long as it is alive, however the call returns
+struct CallDepthGuard;
+impl CallDepthGuard {
+    fn enter(function_name: &str) -> Result<CallDepthGuard, GlangError> {
+        let depth = CALL_DEPTH.with(|depth| {
+            depth.set(depth.get() + 1);
+            depth.get()
+        });
+        let guard = CallDepthGuard;
+        if depth > max_call_depth() {
+            return Err(GlangError::runtime(format!("Too many nested function calls, more than {} deep", max_call_depth())).with_code("E0309")
+                .with_help(format!("check that the recursion in {:?} reaches a case that returns without calling it again", function_name)))
         }
+        Ok(guard)
+    }
+}
+impl Drop for CallDepthGuard {
+    fn drop(&mut self) {
+        CALL_DEPTH.with(|depth| depth.set(depth.get() - 1));
     }
 }
 
-fn eval_boolean_unary_expr(right: &RuntimeVal, operator: &str) -> RuntimeVal {
-    match operator {
-        "!" => {
-            let right_value = right.runtime_val_type.extract_bool_value().unwrap();
-            RuntimeVal {
-                runtime_val_type: RuntimeValType::Boolean(!*right_value)
-            }
-        },
-        _ => {
-            panic!()
+pub fn call_function(function: &environment::Function, arguments: &[RuntimeVal]) -> Result<RuntimeVal, GlangError>{
+    if arguments.len() != function.parameters.len() {
+        return Err(GlangError::runtime(format!("Function {:?} expects {} argument(s), got {}", function.name, function.parameters.len(), arguments.len())).with_code("E0305"))
+    }
+    let _depth = CallDepthGuard::enter(&function.name)?;
+
+    let new_env = environment::new_env(Some(function.closure.clone()));
+    for (parameter, argument) in function.parameters.iter().zip(arguments.iter()) {
+        environment::declare_variable(new_env.clone(), parameter, argument)?;
+    }
+
+    let function_scope = parser::Node { node_type: parser::NodeType::Scope, value: None, body: function.content.clone(), span: lexer::Span::default() };
+    // Only the returned value is kept before the scope is released, so a function that defines closures but returns none of them frees its scope
+    let result = eval_program(&function_scope, new_env.clone()).map(|result| match result.runtime_val_type {
+        RuntimeValType::Return(value) => *value,
+        _ => RuntimeVal { runtime_val_type: RuntimeValType::Null }
+    });
+    environment::release_scope(new_env);
+    result
+}
+
+pub fn eval_function_call(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let identifier_string = node.node_type.extract_fncall_identifier().unwrap();
+
+    // Arguments are evaluated in the caller's environment before any parameter is bound
+    let arguments = eval_arguments(&node.body, env.clone())?;
+    call_by_name(identifier_string, &arguments, env)
+}
+
+pub fn call_by_name(name: &str, arguments: &[RuntimeVal], env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    // User defined functions shadow native functions, which shadow builtins of the same name
+    if !environment::has_variable(env.clone(), name) {
+        if let Some(native) = environment::lookup_native(env.clone(), name) {
+            return call_native(&native, arguments)
+        }
+        if let Some(result) = builtins::call_builtin(name, arguments, env.clone())? {
+            return Ok(result)
         }
     }
+    let function = environment::lookup_function(env, name)?;
+    call_function(&function, arguments)
 }
 
-fn eval_unary_expr(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal {
-    let right = eval(&node.body[0], env.clone());
-    if matches!(right.runtime_val_type, RuntimeValType::NumericInteger(_)){
-        eval_numeric_unary_expr(&right, node.node_type.extract_unexp_operator().unwrap())
-    } else if matches!(right.runtime_val_type, RuntimeValType::Boolean(_)) {
-        eval_boolean_unary_expr(&right, node.node_type.extract_unexp_operator().unwrap())
-    } else {
-        panic!("Incorrect type")
+pub fn call_native(native: &environment::NativeFunction, arguments: &[RuntimeVal]) -> Result<RuntimeVal, GlangError>{
+    if let Some(arity) = native.arity && arguments.len() != arity {
+        return Err(GlangError::runtime(format!("Function {:?} expects {} argument(s), got {}", native.name, arity, arguments.len())).with_code("E0305"))
     }
-    // panic!("{:?}",node.node_type.extract_unexp_operator().unwrap())
+    (native.function)(arguments)
 }
 
-pub fn eval_numeric_binary_expr(left: &RuntimeVal, right: &RuntimeVal, operator: &str) -> RuntimeVal{
-    match operator {
-        "+" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericInteger(*left_value + *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value + *right_value)
-                        }
-                    },
-
-                    _ => panic!()
-                }
+pub fn eval_call(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let callee = eval(&node.body[0], env.clone())?;
+    let arguments = eval_arguments(&node.body[1..], env)?;
+    match callee.runtime_val_type {
+        RuntimeValType::Function(function) => call_function(&function, &arguments),
+        other => Err(GlangError::runtime(format!("Value of type {} is not callable", other.type_name())).with_code("E0302"))
+    }
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value + right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
-        },
-        "-" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericInteger(*left_value - *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value - *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+pub fn array_index(index: &RuntimeVal, length: usize) -> Result<usize, GlangError>{
+    match index.runtime_val_type {
+        RuntimeValType::NumericInteger(value) if value >= 0 && (value as usize) < length => Ok(value as usize),
+        RuntimeValType::NumericInteger(value) => Err(GlangError::runtime(format!("Index out of bounds - index {} with length {}", value, length)).with_code("E0304")),
+        RuntimeValType::BigInteger(ref value) => Err(GlangError::runtime(format!("Index out of bounds - index {} with length {}", value, length)).with_code("E0304")),
+        ref other => Err(GlangError::runtime(format!("Array index must be an integer, got {}", other.type_name())).with_code("E0302"))
+    }
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value - right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
-        },
-        "*" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericInteger(*left_value * *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value * *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+pub fn eval_index(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let target = eval(&node.body[0], env.clone())?;
+    let index = eval(&node.body[1], env)?;
+    read_index(&target, &index)
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value * right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
+pub fn read_index(target: &RuntimeVal, index: &RuntimeVal) -> Result<RuntimeVal, GlangError>{
+    match &target.runtime_val_type {
+        RuntimeValType::Array(elements) => {
+            let elements = elements.borrow();
+            Ok(elements[array_index(index, elements.len())?].clone())
         },
-        "/" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericInteger(*left_value / *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value / *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
-
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value / right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
+        RuntimeValType::Map(entries) => {
+            let key = MapKey::from_value(index)?;
+            match entries.borrow().get(&key) {
+                Some(value) => Ok(value.clone()),
+                None => Err(GlangError::runtime(format!("Key not found in map - {}", index.repr())).with_code("E0304"))
             }
         },
-        "%" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericInteger(*left_value % *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value % *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
-
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericFloat(*left_value % right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
+        RuntimeValType::StringLiteral(string_) => {
+            // Strings are indexed by character, so multi-byte characters count once
+            let position = array_index(index, string_.chars().count())?;
+            Ok(RuntimeVal::from(string_.chars().nth(position).unwrap().to_string()))
         },
-        "**" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::NumericInteger(left_value.pow(*right_value as u32))
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        // let left_value = left_type.extract_float_value().unwrap();
-                        // let right_value = right_type.extract_float_value().unwrap();
-                        // RuntimeVal {
-                        //     runtime_val_type: RuntimeValType::NumericFloat(*left_value / *right_value)
-                        // }
-                        panic!("Power unsupported for floats as of now")
-                    }
-                    _ => panic!()
-                }
+        other => Err(GlangError::runtime(format!("Cannot index into {}", other.type_name())).with_code("E0302"))
+    }
+}
 
-            } else {
-                panic!("Mismatched types")
-            }
+pub fn eval_index_assignment(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let target = eval(&node.body[0], env.clone())?;
+    let index = eval(&node.body[1], env.clone())?;
+    let value = eval(&node.body[2], env)?;
+    write_index(&target, &index, value)?;
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Null })
+}
+
+pub fn write_index(target: &RuntimeVal, index: &RuntimeVal, value: RuntimeVal) -> Result<(), GlangError>{
+    match &target.runtime_val_type {
+        RuntimeValType::Array(elements) => {
+            let mut elements = elements.borrow_mut();
+            let position = array_index(index, elements.len())?;
+            elements[position] = value;
         },
-        "==" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value==*right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value==*right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        RuntimeValType::Map(entries) => {
+            entries.borrow_mut().insert(MapKey::from_value(index)?, value);
+        },
+        RuntimeValType::StringLiteral(_) => return Err(GlangError::runtime("Strings cannot be changed in place".to_string()).with_code("E0302").with_help("build a new string instead, e.g. with `slice` and `+`".to_string())),
+        other => return Err(GlangError::runtime(format!("Cannot index into {}", other.type_name())).with_code("E0302"))
+    }
+    Ok(())
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value == right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
+fn field_suggestion(error: GlangError, definition: &environment::Struct, field_name: &str) -> GlangError {
+    let members = definition.fields.iter().map(String::as_str).chain(definition.methods.iter().map(|method| method.name.as_str()));
+    match diagnostics::suggest(field_name, members) {
+        Some(suggestion) => error.with_help(format!("did you mean `{}`?", suggestion)),
+        None => error
+    }
+}
+
+pub fn eval_struct_instance(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let struct_identifier = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+    let definition = environment::lookup_struct(env.clone(), struct_identifier)?;
+
+    let mut fields: Vec<environment::Variable> = vec![];
+    for pair in node.body.chunks(2) {
+        let field_name = pair[0].value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string();
+        if !definition.fields.contains(&field_name) {
+            return Err(field_suggestion(GlangError::runtime(format!("Struct {:?} has no field {:?}", definition.name, field_name)).with_code("E0307"), &definition, &field_name))
+        } else if fields.iter().any(|field| field.name == field_name) {
+            return Err(GlangError::runtime(format!("Field {:?} given more than once for struct {:?}", field_name, definition.name)).with_code("E0307"))
+        }
+        fields.push(environment::Variable { name: field_name, value: eval(&pair[1], env.clone())? });
+    }
+
+    // Fields are stored in declaration order
+    let mut ordered_fields: Vec<environment::Variable> = vec![];
+    for field_name in definition.fields.iter() {
+        match fields.iter().position(|field| &field.name == field_name) {
+            Some(position) => ordered_fields.push(fields.remove(position)),
+            None => return Err(GlangError::runtime(format!("Missing field {:?} for struct {:?}", field_name, definition.name)).with_code("E0307"))
+        }
+    }
+
+    Ok(RuntimeVal {
+        runtime_val_type: RuntimeValType::Struct(Rc::new(RefCell::new(StructInstance { definition, fields: ordered_fields })))
+    })
+}
+
+pub fn eval_field_access(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let field_name = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+    let target = eval(&node.body[0], env)?;
+    read_field(&target, field_name)
+}
+
+pub fn read_field(target: &RuntimeVal, field_name: &str) -> Result<RuntimeVal, GlangError>{
+    match &target.runtime_val_type {
+        RuntimeValType::Struct(instance) => {
+            let instance = instance.borrow();
+            match instance.fields.iter().find(|field| field.name == field_name) {
+                Some(field) => Ok(field.value.clone()),
+                None => Err(field_suggestion(GlangError::runtime(format!("Struct {:?} has no field {:?}", instance.definition.name, field_name)).with_code("E0307"), &instance.definition, field_name))
             }
         },
-        "!=" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value != *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value != *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        RuntimeValType::UserData(userdata) => userdata.host().get_field(field_name),
+        other => Err(GlangError::runtime(format!("Cannot access field {:?} of {}", field_name, other.type_name())).with_code("E0302"))
+    }
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value != right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
+pub fn eval_field_assignment(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let field_name = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+    let target = eval(&node.body[0], env.clone())?;
+    let value = eval(&node.body[1], env)?;
+    write_field(&target, field_name, value)?;
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Null })
+}
+
+pub fn write_field(target: &RuntimeVal, field_name: &str, value: RuntimeVal) -> Result<(), GlangError>{
+    match &target.runtime_val_type {
+        RuntimeValType::Struct(instance) => {
+            let mut instance = instance.borrow_mut();
+            let struct_name = instance.definition.name.clone();
+            match instance.fields.iter_mut().find(|field| field.name == field_name) {
+                Some(field) => field.value = value,
+                None => return Err(GlangError::runtime(format!("Struct {:?} has no field {:?}", struct_name, field_name)).with_code("E0307"))
             }
         },
-        ">" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value > *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value > *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        RuntimeValType::UserData(userdata) => userdata.host().set_field(field_name, value)?,
+        other => return Err(GlangError::runtime(format!("Cannot assign field {:?} of {}", field_name, other.type_name())).with_code("E0302"))
+    }
+    Ok(())
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value > right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
+pub fn eval_compound_assignment(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    // The target's container and index are evaluated once, so a[next()] += 1 only calls next once
+    let operator = match &node.node_type {
+        parser::NodeType::CompoundAssignment(operator) => operator.as_str(),
+        _ => unreachable!()
+    };
+    let target = &node.body[0];
+    match target.node_type {
+        parser::NodeType::Identifier => {
+            let name = target.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+            let current = eval(target, env.clone())?;
+            let value = eval(&node.body[1], env.clone())?;
+            let result = apply_binary_operator(&current, &value, operator)?;
+            environment::assign_variable(env, name, &result)
         },
-        "<" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value < *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value < *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        parser::NodeType::Index => {
+            let container = eval(&target.body[0], env.clone())?;
+            let index = eval(&target.body[1], env.clone())?;
+            let current = read_index(&container, &index).map_err(|error| error.with_span(target.span))?;
+            let value = eval(&node.body[1], env)?;
+            let result = apply_binary_operator(&current, &value, operator)?;
+            write_index(&container, &index, result)?;
+            Ok(RuntimeVal { runtime_val_type: RuntimeValType::Null })
+        },
+        parser::NodeType::FieldAccess => {
+            let field_name = target.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+            let instance = eval(&target.body[0], env.clone())?;
+            let current = read_field(&instance, field_name).map_err(|error| error.with_span(target.span))?;
+            let value = eval(&node.body[1], env)?;
+            let result = apply_binary_operator(&current, &value, operator)?;
+            write_field(&instance, field_name, result)?;
+            Ok(RuntimeVal { runtime_val_type: RuntimeValType::Null })
+        },
+        _ => Err(GlangError::runtime(format!("Cannot assign to {:?}", target.node_type)))
+    }
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value < right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
+pub fn eval_method_call(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let method_name = node.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+    let target = eval(&node.body[0], env.clone())?;
+    let arguments = eval_arguments(&node.body[1..], env.clone())?;
+    match &target.runtime_val_type {
+        RuntimeValType::Struct(instance) => {
+            let definition = instance.borrow().definition.clone();
+            let method = match definition.methods.iter().find(|method| method.name == method_name) {
+                Some(method) => method.clone(),
+                None => return Err(GlangError::runtime(format!("Struct {:?} has no method {:?}", definition.name, method_name)).with_code("E0307"))
+            };
+
+            // The instance is bound as self in a scope between the method and its closure
+            let method_env = environment::new_env(Some(method.closure.clone()));
+            environment::declare_variable(method_env.clone(), "self", &target)?;
+            let bound_method = environment::Function { closure: method_env, ..(*method).clone() };
+            call_function(&bound_method, &arguments)
+        },
+        RuntimeValType::UserData(userdata) => userdata.host().call_method(method_name, &arguments),
+        RuntimeValType::StringLiteral(_) | RuntimeValType::Array(_) | RuntimeValType::Map(_) => {
+            // Methods on builtin types are the builtins taking the value as their first argument, e.g. s.upper() is upper(s)
+            let mut receiver_and_arguments = vec![target.clone()];
+            receiver_and_arguments.extend(arguments);
+            match builtins::call_builtin(method_name, &receiver_and_arguments, env)? {
+                Some(result) => Ok(result),
+                None => Err(GlangError::runtime(format!("Cannot call method {:?} on {}", method_name, target.runtime_val_type.type_name())).with_code("E0307"))
             }
         },
-        ">=" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value >= *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value >= *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        other => Err(GlangError::runtime(format!("Cannot call method {:?} on {}", method_name, other.type_name())).with_code("E0302"))
+    }
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value >= right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
+pub fn eval_return(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    let value = match node.body.first() {
+        Some(expr) => eval(expr, env)?,
+        None => RuntimeVal { runtime_val_type: RuntimeValType::Null }
+    };
+    Ok(RuntimeVal {
+        runtime_val_type: RuntimeValType::Return(Box::new(value))
+    })
+}
+
+fn eval_numeric_unary_expr(right: &RuntimeVal, operator: &str) -> Result<RuntimeVal, GlangError> {
+    match operator {
+        "!" | "~" => {
+            let right_value = right.runtime_val_type.extract_int_value().unwrap();
+            Ok(RuntimeVal {
+                runtime_val_type: RuntimeValType::NumericInteger(!*right_value)
+            })
         },
-        "<=" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::NumericInteger(_) => {
-                        let left_value = left_type.extract_int_value().unwrap();
-                        let right_value = right_type.extract_int_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value <= *right_value)
-                        }
-                    },
-                    RuntimeValType::NumericFloat(_) => {
-                        let left_value = left_type.extract_float_value().unwrap();
-                        let right_value = right_type.extract_float_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value <= *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        _ => {
+            Err(GlangError::runtime(format!("Invalid unary operator {} for integer", operator)).with_code("E0302"))
+        }
+    }
+}
 
-            } else {
-                if matches!(right_type, RuntimeValType::NumericInteger(_)) {
-                    let left_value = left_type.extract_float_value().unwrap();
-                    let right_value = right_type.extract_int_value().unwrap();
-                    let right_value_float: f64 = *right_value as f64;
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value <= right_value_float)
-                        }
-                } else {
-                    panic!("Cannot conver type string into float");
-                }
-            }
+fn eval_boolean_unary_expr(right: &RuntimeVal, operator: &str) -> Result<RuntimeVal, GlangError> {
+    match operator {
+        "!" => {
+            let right_value = right.runtime_val_type.extract_bool_value().unwrap();
+            Ok(RuntimeVal {
+                runtime_val_type: RuntimeValType::Boolean(!*right_value)
+            })
         },
         _ => {
-            panic!("Invalid operator for numeric type")
+            Err(GlangError::runtime(format!("Invalid unary operator {} for boolean", operator)).with_code("E0302"))
         }
     }
 }
 
-fn eval_bool_binary_expr(left: &RuntimeVal, right: &RuntimeVal, operator: &str) -> RuntimeVal{
+fn eval_unary_expr(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    let right = eval(&node.body[0], env)?;
+    let operator = node.node_type.extract_unexp_operator().unwrap();
+    match right.runtime_val_type {
+        RuntimeValType::NumericInteger(_) => eval_numeric_unary_expr(&right, operator),
+        RuntimeValType::Boolean(_) => eval_boolean_unary_expr(&right, operator),
+        RuntimeValType::BigInteger(_) => Err(GlangError::runtime(format!("Unary {} only supports integers that fit in 64 bits", operator)).with_code("E0308")),
+        ref other => Err(GlangError::runtime(format!("Invalid operand type {} for unary {}", other.type_name(), operator)).with_code("E0302"))
+    }
+}
+
+// Operands of an arithmetic or comparison operator once promoted to a common type
+enum NumericOperands {
+    Integers(i64, i64),
+    BigIntegers(BigInt, BigInt),
+    Floats(f64, f64)
+}
+
+fn promote(left: &RuntimeVal, right: &RuntimeVal) -> Option<NumericOperands> {
+    // An integer meeting a float becomes a float, on either side of any operator.
+    // A big integer meeting an integer makes both big integers
+    let as_float = |value: &RuntimeValType| match value {
+        RuntimeValType::NumericInteger(int) => Some(*int as f64),
+        RuntimeValType::BigInteger(big) => Some(big.to_f64()),
+        RuntimeValType::NumericFloat(float) => Some(*float),
+        _ => None
+    };
+    let as_big = |value: &RuntimeValType| match value {
+        RuntimeValType::NumericInteger(int) => Some(BigInt::from_i64(*int)),
+        RuntimeValType::BigInteger(big) => Some(big.clone()),
+        _ => None
+    };
+    match (&left.runtime_val_type, &right.runtime_val_type) {
+        (RuntimeValType::NumericInteger(left_value), RuntimeValType::NumericInteger(right_value)) => Some(NumericOperands::Integers(*left_value, *right_value)),
+        (RuntimeValType::NumericFloat(_), _) | (_, RuntimeValType::NumericFloat(_)) => Some(NumericOperands::Floats(as_float(&left.runtime_val_type)?, as_float(&right.runtime_val_type)?)),
+        (left_type, right_type) => Some(NumericOperands::BigIntegers(as_big(left_type)?, as_big(right_type)?))
+    }
+}
+
+// Integer results are kept as i64 whenever they fit
+pub fn integer_value(big: BigInt) -> RuntimeValType {
+    match big.to_i64() {
+        Some(int) => RuntimeValType::NumericInteger(int),
+        None => RuntimeValType::BigInteger(big)
+    }
+}
+
+fn compare<T: PartialOrd>(left: T, right: T, operator: &str) -> Option<bool> {
     match operator {
-        "==" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::Boolean(_) => {
-                        let left_value = left_type.extract_bool_value().unwrap();
-                        let right_value = right_type.extract_bool_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value==*right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+        "==" => Some(left == right),
+        "!=" => Some(left != right),
+        "<" => Some(left < right),
+        "<=" => Some(left <= right),
+        ">" => Some(left > right),
+        ">=" => Some(left >= right),
+        _ => None
+    }
+}
 
-            } else {
-                panic!("Mismatched types")
-            }
-        },
-        "!=" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::Boolean(_) => {
-                        let left_value = left_type.extract_bool_value().unwrap();
-                        let right_value = right_type.extract_bool_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value != *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+fn division_by_zero() -> GlangError {
+    GlangError::runtime("Division by zero".to_string()).with_code("E0303")
+}
 
-            } else {
-                panic!("Mismatched types")
-            }
-        },
-        "&&" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::Boolean(_) => {
-                        let left_value = left_type.extract_bool_value().unwrap();
-                        let right_value = right_type.extract_bool_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value && *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+fn integer_too_large(operator: &str) -> GlangError {
+    GlangError::runtime(format!("Result of {} is too large to compute", operator)).with_code("E0308")
+}
 
-            } else {
-                panic!("Mismatched types")
-            }
-        },
-        "||" => {
-            let left_type = &left.runtime_val_type;
-            let right_type = &right.runtime_val_type;
-
-            if std::mem::discriminant(left_type) == std::mem::discriminant(right_type){
-                match left_type {
-                    RuntimeValType::Boolean(_) => {
-                        let left_value = left_type.extract_bool_value().unwrap();
-                        let right_value = right_type.extract_bool_value().unwrap();
-                        RuntimeVal {
-                            runtime_val_type: RuntimeValType::Boolean(*left_value || *right_value)
-                        }
-                    }
-                    _ => panic!()
-                }
+fn invalid_numeric_operator(operator: &str) -> GlangError {
+    GlangError::runtime(format!("Invalid operator {} for numeric type", operator)).with_code("E0302")
+}
 
-            } else {
-                panic!("Mismatched types")
-            }
-        },
-        _ => {
-            panic!("Invalid operator for boolean type")
-        }
+fn eval_integer_binary_expr(left: i64, right: i64, operator: &str) -> Result<RuntimeValType, GlangError> {
+    if right == 0 && matches!(operator, "/" | "~/" | "%") {
+        return Err(division_by_zero())
     }
+    // Results that overflow i64 are computed again as big integers
+    let checked = |result: Option<i64>| match result {
+        Some(int) => Ok(RuntimeValType::NumericInteger(int)),
+        None => eval_big_binary_expr(&BigInt::from_i64(left), &BigInt::from_i64(right), operator)
+    };
+    let result = match operator {
+        "+" => checked(left.checked_add(right))?,
+        "-" => checked(left.checked_sub(right))?,
+        "*" => checked(left.checked_mul(right))?,
+        // True division always gives a float, ~/ keeps integers and truncates like %
+        "/" => RuntimeValType::NumericFloat(left as f64 / right as f64),
+        "~/" => checked(left.checked_div(right))?,
+        "%" => checked(left.checked_rem(right))?,
+        "**" => return eval_integer_power(left, right),
+        _ => RuntimeValType::Boolean(compare(left, right, operator).ok_or_else(|| invalid_numeric_operator(operator))?)
+    };
+    Ok(result)
 }
 
-fn eval_binary_expr(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal {
-    let left = eval(&node.body[0], env.clone());
-    let right = eval(&node.body[1], env.clone());
+fn eval_integer_power(base: i64, exponent: i64) -> Result<RuntimeValType, GlangError> {
+    match u32::try_from(exponent).ok().and_then(|exponent| base.checked_pow(exponent)) {
+        Some(int) => Ok(RuntimeValType::NumericInteger(int)),
+        None => eval_big_power(&BigInt::from_i64(base), &BigInt::from_i64(exponent))
+    }
+}
 
-    eval_numeric_binary_expr(&left, &right, node.node_type.extract_binexp_operator().unwrap())
+// Results of * and ** are capped at this many bits, about 79 thousand decimal digits. Multiplying
+// and printing are quadratic, so this keeps each operation well under a second
+const MAX_INTEGER_BITS: u64 = 1 << 18;
 
-    // if matches!(left.runtime_val_type, RuntimeValType::NumericInteger(_)) && matches!(right.runtime_val_type, RuntimeValType::NumericInteger(_)){
-    //     eval_numeric_binary_expr(&left, &right, node.node_type.extract_binexp_operator().unwrap())
-    // } else if matches!(left.runtime_val_type, RuntimeValType::NumericFloat(_)) && matches!(right.runtime_val_type, RuntimeValType::NumericFloat(_)) {
-    //     eval_numeric_binary_expr(&left, &right, node.node_type.extract_binexp_operator().unwrap())
-    // } else if matches!(left.runtime_val_type, RuntimeValType::Boolean(_)) && matches!(right.runtime_val_type, RuntimeValType::Boolean(_)) {
-    //     eval_bool_binary_expr(&left, &right, node.node_type.extract_binexp_operator().unwrap())
-    // } else {
-    //     panic!("Mismatched types")
-    // }
+fn eval_big_power(base: &BigInt, exponent: &BigInt) -> Result<RuntimeValType, GlangError> {
+    if exponent.is_negative() {
+        return eval_float_binary_expr(base.to_f64(), exponent.to_f64(), "**")
+    }
+    // Bases 0, 1 and -1 stay small for any exponent, only its parity matters
+    if base.bit_length() <= 1 {
+        let odd = exponent.div_rem(&BigInt::from_i64(2)).is_some_and(|(_, remainder)| !remainder.is_zero());
+        let exponent = if exponent.is_zero() { 0 } else if odd { 1 } else { 2 };
+        return Ok(integer_value(base.pow(exponent)))
+    }
+    // The result has about exponent * log2(base) bits
+    let base_bits = if base.bit_length() <= 64 { base.to_f64().abs().log2() } else { base.bit_length() as f64 };
+    match exponent.to_i64().and_then(|exponent| u32::try_from(exponent).ok()) {
+        Some(exponent) if base_bits * exponent as f64 <= MAX_INTEGER_BITS as f64 => Ok(integer_value(base.pow(exponent))),
+        _ => Err(integer_too_large("**"))
+    }
 }
 
-pub fn eval_program(program: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> RuntimeVal{
-    let mut last_eval: RuntimeVal = RuntimeVal { runtime_val_type: RuntimeValType::Null };
-    let mut loop_flag = false;
-    let mut if_check_fail_flag = false;
-    let mut loop_condition: Option<&parser::Node> = None;
-    let mut loop_node: Option<&parser::Node> = None;
-    let mut program_counter: usize = 0;
-    while program_counter < program.body.len() {
-        let node = &program.body[program_counter];
-        if matches!(&node.node_type, parser::NodeType::Loop) || loop_flag == true {
-
-            if loop_flag == false {
-                loop_node = Some(&node.body[1]);
-                loop_condition = Some(&node.body[0]);
+fn eval_big_binary_expr(left: &BigInt, right: &BigInt, operator: &str) -> Result<RuntimeValType, GlangError> {
+    if right.is_zero() && matches!(operator, "/" | "~/" | "%") {
+        return Err(division_by_zero())
+    }
+    let result = match operator {
+        "+" => integer_value(left + right),
+        "-" => integer_value(left - right),
+        "*" if left.bit_length() + right.bit_length() > MAX_INTEGER_BITS => return Err(integer_too_large("*")),
+        "*" => integer_value(left * right),
+        "/" => RuntimeValType::NumericFloat(left.to_f64() / right.to_f64()),
+        "~/" => integer_value(left.div_rem(right).unwrap().0),
+        "%" => integer_value(left.div_rem(right).unwrap().1),
+        "**" => return eval_big_power(left, right),
+        _ => RuntimeValType::Boolean(compare(left, right, operator).ok_or_else(|| invalid_numeric_operator(operator))?)
+    };
+    Ok(result)
+}
+
+fn eval_float_binary_expr(left: f64, right: f64, operator: &str) -> Result<RuntimeValType, GlangError> {
+    if right == 0.0 && matches!(operator, "/" | "~/" | "%") || left == 0.0 && right < 0.0 && operator == "**" {
+        return Err(division_by_zero())
+    }
+    let result = match operator {
+        "+" => RuntimeValType::NumericFloat(left + right),
+        "-" => RuntimeValType::NumericFloat(left - right),
+        "*" => RuntimeValType::NumericFloat(left * right),
+        "/" => RuntimeValType::NumericFloat(left / right),
+        "~/" => RuntimeValType::NumericFloat((left / right).trunc()),
+        "%" => RuntimeValType::NumericFloat(left % right),
+        "**" => {
+            let result = left.powf(right);
+            if result.is_nan() && !left.is_nan() && !right.is_nan() {
+                return Err(GlangError::runtime(format!("{} ** {} has no real result", left, right)).with_code("E0300"))
             }
+            RuntimeValType::NumericFloat(result)
+        },
+        _ => RuntimeValType::Boolean(compare(left, right, operator).ok_or_else(|| invalid_numeric_operator(operator))?)
+    };
+    Ok(result)
+}
 
-            let condition_eval = eval(loop_condition.unwrap(),env.clone());
+pub fn eval_numeric_binary_expr(left: &RuntimeVal, right: &RuntimeVal, operator: &str) -> Result<RuntimeVal, GlangError>{
+    let runtime_val_type = match promote(left, right) {
+        Some(NumericOperands::Integers(left_value, right_value)) => eval_integer_binary_expr(left_value, right_value, operator)?,
+        Some(NumericOperands::BigIntegers(left_value, right_value)) => eval_big_binary_expr(&left_value, &right_value, operator)?,
+        Some(NumericOperands::Floats(left_value, right_value)) => eval_float_binary_expr(left_value, right_value, operator)?,
+        None => return Err(type_error(left, right, operator))
+    };
+    Ok(RuntimeVal { runtime_val_type })
+}
 
-            let mut new_env = Rc::new(RefCell::new(environment::Environment {parent: Some(env.clone()), variables: vec![], functions: vec![]})); 
-            
-            // println!("{:?}", loop_flag);
-            let prg = eval_program(&loop_node.unwrap(),new_env);
-            last_eval = prg.clone();
-            // println!("{:?}",prg);
+const BITWISE_OPERATORS: [&str; 5] = ["&", "|", "^", "<<", ">>"];
 
-            loop_flag = true;
-            if matches!(prg.runtime_val_type, RuntimeValType::Break) {
-                program_counter += 1;
-                loop_flag = false;
-            }
-            else if *condition_eval.runtime_val_type.extract_bool_value().unwrap() == false{
-                program_counter += 1;
-                loop_flag = false;
-            }
+fn eval_bitwise_binary_expr(left: &RuntimeVal, right: &RuntimeVal, operator: &str) -> Result<RuntimeVal, GlangError>{
+    let (RuntimeValType::NumericInteger(left_value), RuntimeValType::NumericInteger(right_value)) = (&left.runtime_val_type, &right.runtime_val_type) else {
+        if matches!(left.runtime_val_type, RuntimeValType::BigInteger(_)) || matches!(right.runtime_val_type, RuntimeValType::BigInteger(_)) {
+            return Err(GlangError::runtime(format!("Bitwise {} only supports integers that fit in 64 bits", operator)).with_code("E0308"))
+        }
+        let error = type_error(left, right, operator);
+        // As in C the bitwise operators bind looser than comparisons, which catches out flags & MASK == 0
+        if matches!(left.runtime_val_type, RuntimeValType::Boolean(_)) || matches!(right.runtime_val_type, RuntimeValType::Boolean(_)) {
+            return Err(error.with_help(format!("`{}` binds looser than comparisons, add brackets e.g. `(a {} b) == c`", operator, operator)))
+        }
+        return Err(error)
+    };
+    let result = match operator {
+        "&" => left_value & right_value,
+        "|" => left_value | right_value,
+        "^" => left_value ^ right_value,
+        // >> keeps the sign, bits shifted past either end are dropped
+        "<<" | ">>" => {
+            let shifted = u32::try_from(*right_value).ok().and_then(|amount| if operator == "<<" { left_value.checked_shl(amount) } else { left_value.checked_shr(amount) });
+            shifted.ok_or_else(|| GlangError::runtime(format!("Shift amount must be between 0 and 63, got {}", right_value)).with_code("E0300"))?
+        },
+        _ => return Err(GlangError::runtime(format!("Invalid bitwise operator {}", operator)).with_code("E0302"))
+    };
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::NumericInteger(result) })
+}
 
-        } else if matches!(&node.node_type, parser::NodeType::If) {
-            let if_condition = &node.body[0];
-            let if_condition_eval = eval(if_condition, env.clone());
+fn eval_string_binary_expr(left: &str, right: &str, operator: &str) -> Result<RuntimeVal, GlangError>{
+    // Strings compare by Unicode code point, character by character
+    let result = match operator {
+        "+" => RuntimeValType::StringLiteral(format!("{}{}", left, right)),
+        "==" => RuntimeValType::Boolean(left == right),
+        "!=" => RuntimeValType::Boolean(left != right),
+        "<" => RuntimeValType::Boolean(left < right),
+        "<=" => RuntimeValType::Boolean(left <= right),
+        ">" => RuntimeValType::Boolean(left > right),
+        ">=" => RuntimeValType::Boolean(left >= right),
+        _ => return Err(GlangError::runtime(format!("Invalid operator {} for string type", operator)).with_code("E0302"))
+    };
+    Ok(RuntimeVal { runtime_val_type: result })
+}
 
-            if *if_condition_eval.runtime_val_type.extract_bool_value().unwrap() == true {
-                let mut new_env = Rc::new(RefCell::new(environment::Environment {parent: Some(env.clone()), variables: vec![], functions: vec![]})); 
-                let result = eval_program(node, new_env);
-                if matches!(&result.runtime_val_type, RuntimeValType::Break) {
-                    return result; // Propagate Break immediately
-                }
-                program_counter += 1;
-            } else {
-                if_check_fail_flag = true;
-                program_counter += 1;
-            }
+fn eval_bool_binary_expr(left: bool, right: bool, operator: &str) -> Result<RuntimeVal, GlangError>{
+    // && and || never get here, eval_binary_expr short-circuits them
+    let result = match operator {
+        "==" => left == right,
+        "!=" => left != right,
+        _ => return Err(GlangError::runtime(format!("Invalid operator {} for boolean type", operator)).with_code("E0302"))
+    };
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Boolean(result) })
+}
 
-        } else if matches!(&node.node_type, parser::NodeType::ElseIf) {
-            let if_condition = &node.body[0];
-            let if_condition_eval = eval(if_condition, env.clone());
+fn eval_logical_operand(node: &parser::Node, env: Rc<RefCell<environment::Environment>>, operator: &str) -> Result<bool, GlangError> {
+    match eval(node, env)?.runtime_val_type {
+        RuntimeValType::Boolean(boolean) => Ok(boolean),
+        other => Err(GlangError::runtime(format!("Operands of {} must be booleans, got {}", operator, other.type_name())).with_code("E0302").with_span(node.span))
+    }
+}
 
-            if *if_condition_eval.runtime_val_type.extract_bool_value().unwrap() == true && if_check_fail_flag == true {
-                let mut new_env = Rc::new(RefCell::new(environment::Environment {parent: Some(env.clone()), variables: vec![], functions: vec![]})); 
-                let result = eval_program(node, new_env);
-                if matches!(&result.runtime_val_type, RuntimeValType::Break) {
-                    return result; // Propagate Break immediately
-                }
-                if_check_fail_flag = false;
-                program_counter += 1;
-            } else {
-                if_check_fail_flag = true;
-                program_counter += 1;
-            }
+fn eval_logical_expr(node: &parser::Node, env: Rc<RefCell<environment::Environment>>, operator: &str) -> Result<RuntimeVal, GlangError> {
+    // The right operand is only evaluated when it can change the result, so i < len(a) && a[i] > 0 is safe
+    let left = eval_logical_operand(&node.body[0], env.clone(), operator)?;
+    let result = if left == (operator == "||") { left } else { eval_logical_operand(&node.body[1], env, operator)? };
+    Ok(RuntimeVal { runtime_val_type: RuntimeValType::Boolean(result) })
+}
 
-        } else if matches!(&node.node_type, parser::NodeType::Else){
-            if if_check_fail_flag == true {
-                let mut new_env = Rc::new(RefCell::new(environment::Environment {parent: Some(env.clone()), variables: vec![], functions: vec![]})); 
-                let result = eval_program(node, new_env);
-                if matches!(&result.runtime_val_type, RuntimeValType::Break) {
-                    return result; // Propagate Break immediately
-                }
-                program_counter += 1;
-                if_check_fail_flag = false;
+fn eval_binary_expr(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    let operator = node.node_type.extract_binexp_operator().unwrap();
+    if operator == "&&" || operator == "||" {
+        return eval_logical_expr(node, env, operator)
+    }
+
+    let left = eval(&node.body[0], env.clone())?;
+    let right = eval(&node.body[1], env)?;
+    apply_binary_operator(&left, &right, operator)
+}
+
+pub fn apply_binary_operator(left: &RuntimeVal, right: &RuntimeVal, operator: &str) -> Result<RuntimeVal, GlangError> {
+    if BITWISE_OPERATORS.contains(&operator) {
+        eval_bitwise_binary_expr(left, right, operator)
+    } else if let (RuntimeValType::Boolean(left_boolean), RuntimeValType::Boolean(right_boolean)) = (&left.runtime_val_type, &right.runtime_val_type) {
+        eval_bool_binary_expr(*left_boolean, *right_boolean, operator)
+    } else if let (RuntimeValType::StringLiteral(left_string), RuntimeValType::StringLiteral(right_string)) = (&left.runtime_val_type, &right.runtime_val_type) {
+        eval_string_binary_expr(left_string, right_string, operator)
+    } else if let (RuntimeValType::UserData(left_data), RuntimeValType::UserData(right_data)) = (&left.runtime_val_type, &right.runtime_val_type) {
+        // Userdata only supports equality, which the host decides
+        match operator {
+            "==" => Ok(RuntimeVal { runtime_val_type: RuntimeValType::Boolean(left_data.equals(right_data)) }),
+            "!=" => Ok(RuntimeVal { runtime_val_type: RuntimeValType::Boolean(!left_data.equals(right_data)) }),
+            _ => Err(type_error(left, right, operator))
+        }
+    } else {
+        eval_numeric_binary_expr(left, right, operator)
+    }
+}
+
+fn eval_print_args(arguments: &[parser::Node], env: Rc<RefCell<environment::Environment>>) -> Result<String, GlangError> {
+    // Values are joined by sep and followed by end, which default to a space and a line break
+    let mut values: Vec<String> = vec![];
+    let mut separator = " ".to_string();
+    let mut end = "\n".to_string();
+    for argument in arguments {
+        if matches!(argument.node_type, parser::NodeType::NamedArgument) {
+            let name = argument.value.as_ref().unwrap().token_type.extract_str_value().unwrap();
+            let value = eval(&argument.body[0], env.clone())?;
+            let text = value.expect_str().map_err(|error| error.with_span(argument.span))?.to_string();
+            if name == "sep" {
+                separator = text;
             } else {
-                program_counter += 1;
-            }
-        } else if matches!(&node.node_type, parser::NodeType::Scope){
-            let mut new_env = Rc::new(RefCell::new(environment::Environment {parent: Some(env.clone()), variables: vec![], functions: vec![]})); 
-            
-            let result = eval_program(node, new_env);
-            if matches!(&result.runtime_val_type, RuntimeValType::Break) {
-                return result; // Propagate Break immediately
+                end = text;
             }
-            program_counter += 1;
-            // println!("{:?}", new_env);
-            
-        } else if let parser::NodeType::Function(op) = &node.node_type {
-            environment::declare_function(env.clone(), op, &node.body);
-            // println!("hi");
-            program_counter += 1; 
-        } else if let parser::NodeType::FunctionCall(op) = &node.node_type{
-            let mut function = environment::lookup_function(env.clone(), op);
-            let function_immut_ref = function.clone();
-            for i in 0..node.body.len() {
-                function.body[0].body[i] = parser::Node {
-                    node_type: parser::NodeType::Declaration,
-                    value: Some(lexer::Token { token_type: function_immut_ref.body[0].body[i].value.clone().unwrap().token_type}),
-                    body: vec![parser::Node { node_type: parser::NodeType::NumericLiteral, value: node.body[i].value.clone(), body: vec![]}]
-                
-                };
-            }
-            // println!("{:?}",function);
-            let mut new_env = Rc::new(RefCell::new(environment::Environment {parent: Some(env.clone()), variables: vec![], functions: vec![]})); 
-            // println!("{:?}", function);
-            eval_program(&function, new_env);
-            program_counter += 1;
-        } else if matches!(&node.node_type, parser::NodeType::Print) {
-            let val = eval(&node.body[0], env.clone());
-            // println!("{:?}",&node.body[0]);
-            match val.runtime_val_type {
-                RuntimeValType::NumericInteger(_) => {
-                    println!("{:?}",val.runtime_val_type.extract_int_value().unwrap());
-                },
-                RuntimeValType::NumericFloat(_) => {
-                    println!("{:?}",val.runtime_val_type.extract_float_value().unwrap());
-                },
-                RuntimeValType::Boolean(_) => {
-                    println!("{:?}",val.runtime_val_type.extract_bool_value().unwrap());
-                },
-                RuntimeValType::StringLiteral(_) => {
-                    println!("{:?}",val.runtime_val_type.extract_string_value().unwrap());
-                },
-                _=> panic!("Invalid type to print")
-            }
-            program_counter += 1;
-            // println!(val.va)
-        } else if matches!(&node.node_type, parser::NodeType::EOL){
-            program_counter += 1;
-            continue
         } else {
-            last_eval = eval(&node, env.clone());
-            if matches!(last_eval.runtime_val_type, RuntimeValType::Break){
-                return last_eval;
-            } else {
-                program_counter += 1;
+            values.push(eval(argument, env.clone())?.to_string());
+        }
+    }
+    Ok(format!("{}{}", values.join(&separator), end))
+}
+
+// Runs a block in a scope of its own, freeing the scope afterwards unless something still needs it
+fn eval_block(block: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError> {
+    let block_env = environment::new_env(Some(env));
+    let result = eval_program(block, block_env.clone());
+    environment::release_scope(block_env);
+    result
+}
+
+// Like eval_block for the body of a branch or loop, which has no value. Only a break or return inside it is passed on,
+// anything else is dropped before the scope is released so it cannot keep the scope alive
+fn eval_branch(block: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<Option<RuntimeVal>, GlangError> {
+    let block_env = environment::new_env(Some(env));
+    let result = eval_program(block, block_env.clone()).map(|result| matches!(result.runtime_val_type, RuntimeValType::Break | RuntimeValType::Return(_)).then_some(result));
+    environment::release_scope(block_env);
+    result
+}
+
+// Runs an if, elif or else. The flag records whether every branch of the chain so far has failed,
+// an elif or else only runs when it has
+fn eval_conditional(node: &parser::Node, env: Rc<RefCell<environment::Environment>>, chain_failed: &mut bool) -> Result<Option<RuntimeVal>, GlangError> {
+    let (condition, block) = match node.node_type {
+        parser::NodeType::If => (Some(&node.body[0]), &node.body[1]),
+        parser::NodeType::ElseIf if *chain_failed => (Some(&node.body[0]), &node.body[1]),
+        parser::NodeType::Else if *chain_failed => (None, &node.body[0]),
+        _ => return Ok(None) // An earlier branch was taken
+    };
+    if let Some(condition) = condition && !eval_condition(condition, env.clone())? {
+        *chain_failed = true;
+        return Ok(None)
+    }
+    *chain_failed = false;
+    eval_branch(block, env)
+}
+
+// Gives the return that ended the loop, if any
+fn eval_loop(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<Option<RuntimeVal>, GlangError> {
+    // Condition is checked before every iteration, including the first
+    while eval_condition(&node.body[0], env.clone())? {
+        match eval_branch(&node.body[1], env.clone())? {
+            Some(result) if matches!(result.runtime_val_type, RuntimeValType::Return(_)) => return Ok(Some(result)), // Return unwinds through the loop
+            Some(_) => break,
+            None => {}
+        }
+    }
+    Ok(None)
+}
+
+fn eval_struct_definition(node: &parser::Node, name: &str, env: Rc<RefCell<environment::Environment>>) -> Result<Option<RuntimeVal>, GlangError> {
+    let mut fields: Vec<String> = vec![];
+    let mut methods: Vec<Rc<environment::Function>> = vec![];
+    for member in node.body.iter() {
+        if let parser::NodeType::Function(method_name) = &member.node_type {
+            let (parameters, content) = function_definition_parts(member);
+            methods.push(Rc::new(environment::Function { name: method_name.to_string(), parameters, content: content.to_vec(), closure: env.clone() }));
+        } else {
+            fields.push(member.value.as_ref().unwrap().token_type.extract_str_value().unwrap().to_string());
+        }
+    }
+    environment::declare_struct(env, environment::Struct { name: name.to_string(), fields, methods }).map_err(|error| error.with_span(node.span))?;
+    Ok(None)
+}
+
+fn eval_function_declaration(node: &parser::Node, name: &str, env: Rc<RefCell<environment::Environment>>) -> Result<Option<RuntimeVal>, GlangError> {
+    let (parameters, content) = function_definition_parts(node);
+    environment::declare_function(env, name, &parameters, content).map_err(|error| error.with_span(node.span))?;
+    Ok(None)
+}
+
+fn eval_print(node: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<Option<RuntimeVal>, GlangError> {
+    let text = eval_print_args(&node.body, env.clone())?;
+    environment::write_output(env, environment::Stream::Output, &text).map_err(|error| error.with_span(node.span))?;
+    Ok(None)
+}
+
+pub fn eval_program(program: &parser::Node, env: Rc<RefCell<environment::Environment>>) -> Result<RuntimeVal, GlangError>{
+    // Statements are run by helpers so this frame stays small, it is on the stack once for every block being run
+    let mut last_eval: RuntimeVal = RuntimeVal { runtime_val_type: RuntimeValType::Null };
+    let mut if_check_fail_flag = false;
+    for node in program.body.iter() {
+        let value = match &node.node_type {
+            parser::NodeType::Loop => eval_loop(node, env.clone()),
+            parser::NodeType::If | parser::NodeType::ElseIf | parser::NodeType::Else => eval_conditional(node, env.clone(), &mut if_check_fail_flag),
+            parser::NodeType::Scope => eval_branch(node, env.clone()),
+            parser::NodeType::Struct(name) => eval_struct_definition(node, name, env.clone()),
+            parser::NodeType::Function(name) => eval_function_declaration(node, name, env.clone()),
+            parser::NodeType::Print => eval_print(node, env.clone()),
+            parser::NodeType::EOL => continue,
+            _ => eval(node, env.clone()).map(Some)
+        }?;
+        // Statements without a value leave the last one in place, break and return end the program early
+        if let Some(value) = value {
+            if matches!(value.runtime_val_type, RuntimeValType::Break | RuntimeValType::Return(_)) {
+                return Ok(value)
             }
+            last_eval = value;
         }
     }
-    last_eval
+    Ok(last_eval)
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+use super::eval;
+use super::environment;
+use super::parser;
+use super::error::GlangError;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::io::Write;
+
+// Embedding entry point for Rust hosts. Globals, functions and structs persist between calls to eval,
+// so a host can load a script once and then call into it, e.g.
+//
+// let mut interpreter = glang::Interpreter::new();
+// interpreter.set_global("limit", 10.into());
+// interpreter.eval("func double(x) { return x * 2 }")?;
+// let result = interpreter.call("double", &[21.into()])?;
+pub struct Interpreter {
+    globals: Rc<RefCell<environment::Environment>>
+}
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter { globals: environment::new_env(None) }
+    }
+
+    // Runs a snippet in the global scope and returns the value of its last statement
+    pub fn eval(&mut self, source: &str) -> Result<eval::RuntimeVal, GlangError> {
+        let ast = parser::generate_ast(source.to_string())?;
+        let result = eval::eval_program(&ast, self.globals.clone())?;
+        match result.runtime_val_type {
+            eval::RuntimeValType::Return(value) => Ok(*value),
+            _ => Ok(result)
+        }
+    }
+
+    // Declares the global, or overwrites it if the name is already taken
+    pub fn set_global(&mut self, name: &str, value: eval::RuntimeVal) {
+        let mut globals = self.globals.borrow_mut();
+        match globals.variables.iter_mut().find(|variable| variable.name == name) {
+            Some(variable) => variable.value = value,
+            None => globals.variables.push(environment::Variable { name: name.to_string(), value })
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<eval::RuntimeVal> {
+        environment::lookup_variable(self.globals.clone(), name).ok()
+    }
+
+    // Calls a function by name, resolved the same way as a call from a script
+    pub fn call(&mut self, name: &str, arguments: &[eval::RuntimeVal]) -> Result<eval::RuntimeVal, GlangError> {
+        eval::call_by_name(name, arguments, self.globals.clone())
+    }
+
+    // Makes a Rust closure callable from scripts. The arity is checked before the closure runs,
+    // pass None to accept any number of arguments
+    pub fn register_function<F>(&mut self, name: &str, arity: Option<usize>, function: F)
+    where F: Fn(&[eval::RuntimeVal]) -> Result<eval::RuntimeVal, GlangError> + 'static {
+        environment::declare_native(self.globals.clone(), environment::NativeFunction { name: name.to_string(), arity, function: Box::new(function) });
+    }
+
+    // Sends print to the writer instead of stdout. The host keeps its own handle to read what was written,
+    // e.g. an Rc<RefCell<Vec<u8>>> to capture output
+    pub fn set_output<W: Write + 'static>(&mut self, writer: Rc<RefCell<W>>) {
+        self.globals.borrow_mut().output = Some(environment::OutputSink(writer));
+    }
+
+    // Sends eprint to the writer instead of stderr
+    pub fn set_error_output<W: Write + 'static>(&mut self, writer: Rc<RefCell<W>>) {
+        self.globals.borrow_mut().error_output = Some(environment::OutputSink(writer));
+    }
+
+    // Sets how deeply script functions may call each other before failing with E0309, MAX_CALL_DEPTH by default.
+    // The limit applies to every interpreter on the calling thread, raise it only when the thread's stack has room
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        eval::set_max_call_depth(depth);
+    }
+
+    // Forgets every global, function and struct, native functions and output sinks stay
+    pub fn reset(&mut self) {
+        let previous = self.globals.clone();
+        self.globals = environment::new_env(None);
+        let previous = previous.borrow();
+        let mut globals = self.globals.borrow_mut();
+        globals.natives = previous.natives.clone();
+        globals.output = previous.output.clone();
+        globals.error_output = previous.error_output.clone();
+    }
+}
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
\ No newline at end of file
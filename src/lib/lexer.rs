@@ -1,15 +1,25 @@
+use super::error::GlangError;
+use super::bigint::BigInt;
+
+pub const KEYWORDS: [&str; 12] = ["let", "true", "false", "if", "else", "elif", "loop", "break", "print", "func", "return", "struct"];
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone)]
 pub enum TokenType {
     Integer(i64),
+    BigInteger(BigInt),
     Float(f64),
     Boolean(bool),
     Identifier(String),
     StringLiteral(String),
+    InterpolatedString(Vec<StringPart>),
     Operator(String),
     OpenBracket,
     CloseBracket,
     OpenCurlyBracket,
     CloseCurlyBracket,
+    OpenSquareBracket,
+    CloseSquareBracket,
     Let,
     Punctuation(String),
     If,
@@ -22,6 +32,8 @@ pub enum TokenType {
     Function,
     FunctionCall(String),
     Return,
+    Struct,
+    DocComment(String), // Only found in TokenStream::doc_comments, never among the tokens
     EOF
 }
 impl TokenType {
@@ -37,6 +49,8 @@ impl TokenType {
             "break" => Some(TokenType::Break),
             "print" => Some(TokenType::Print),
             "func" => Some(TokenType::Function),
+            "return" => Some(TokenType::Return),
+            "struct" => Some(TokenType::Struct),
             _=>None
         }
         
@@ -69,8 +83,6 @@ impl TokenType {
     pub fn extract_str_value(&self) -> Option<&str> {
         if let TokenType::Identifier(str) = self {
             Some(str)
-        } else if  let TokenType::Identifier(str) = self {
-            Some(str)
         } else if  let TokenType::StringLiteral(str) = self {
             Some(str)
         } else if  let TokenType::Punctuation(str) = self {
@@ -100,48 +112,93 @@ impl TokenType {
 
 }
 
+#[derive(Debug, Clone)]
+pub enum StringPart {
+    Text(String),
+    Expression(TokenStream) // Tokens of an embedded {expression}, their spans point into the whole source
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span { // Lines and columns start at 1, a default span means the location is unknown
+    pub offset: usize, // Byte offset of the first character
+    pub length: usize, // Length in bytes
+    pub line: usize,
+    pub column: usize
+}
+impl Span {
+    pub fn to(&self, end: &Span) -> Span {
+        // Covers everything from the start of this span to the end of the other
+        Span {
+            length: (end.offset + end.length).saturating_sub(self.offset),
+            ..*self
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
+    pub span: Span
+}
+impl Token {
+    pub fn new(token_type: TokenType) -> Token {
+        // The span is filled in by the tokeniser once the whole token has been read
+        Token { token_type, span: Span::default() }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TokenStream {
     pub tokens: Vec<Token>,
-    pub current_pos: usize
+    pub current_pos: usize,
+    pub doc_comments: Vec<Token> // /// comments, consecutive lines merged into one token, kept apart from the tokens
 }
 impl TokenStream {
-    // Last in first out
-    pub fn push(&mut self, token: Token){
-        self.tokens.push(token);
+    pub fn doc_comment_before(&self, line: usize) -> Option<Token>{
+        // Finds the doc comment whose last line is directly above the given line
+        self.doc_comments.iter().find(|doc_comment| match &doc_comment.token_type {
+            TokenType::DocComment(text) => doc_comment.span.line + text.matches('\n').count() + 1 == line,
+            _ => false
+        }).cloned()
     }
 
     pub fn pop(&mut self){
-        if self.current_pos < self.tokens.len(){
+        // Never moves past the EOF token, so a truncated script surfaces as a syntax error on EOF
+        if self.current_pos + 1 < self.tokens.len(){
             self.current_pos += 1;
-        } else {
-            panic!("Out of range")
         }
     }
 
     pub fn at(&self) -> Token{
         self.tokens[self.current_pos].clone()
     }
+
+    pub fn span_from(&self, start_pos: usize) -> Span{
+        // Spans from the token at start_pos to the last token consumed
+        let end_pos = if self.current_pos > start_pos { self.current_pos - 1 } else { start_pos };
+        self.tokens[start_pos].span.to(&self.tokens[end_pos].span)
+    }
 }
 
 #[derive(Debug, Clone)]
 struct DataStream {
     pub characters: Vec<char>,
-    pub current_pos: usize
+    pub current_pos: usize,
+    pub byte_offset: usize,
+    pub line: usize,
+    pub column: usize
 }
 impl DataStream {
-    // Last in first out
-    pub fn push(&mut self, character: char){
-        self.characters.push(character);
-    }
-
     pub fn pop(&mut self){
         if self.current_pos < self.characters.len(){
+            let character = self.characters[self.current_pos];
+            self.byte_offset += character.len_utf8();
+            if character == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
             self.current_pos += 1;
         } else {
             panic!("Out of range")
@@ -149,64 +206,264 @@ impl DataStream {
     }
 
     pub fn at(&self) -> char{
-        self.characters[self.current_pos].clone()
+        self.characters[self.current_pos]
+    }
+
+    pub fn position(&self) -> Span{
+        Span { offset: self.byte_offset, length: 0, line: self.line, column: self.column }
+    }
+
+    pub fn span_from(&self, start: &Span) -> Span{
+        Span { length: self.byte_offset - start.offset, ..*start }
     }
 }
 
 
 
-pub fn tokenise(source: String) -> TokenStream {
-    let mut source_split: Vec<char> = source.chars().collect();
+fn parse_numeral(numeral_string: &str) -> Result<TokenType, GlangError> {
+    if numeral_string.contains("."){
+        match numeral_string.parse::<f64>() {
+            Ok(float_proper) => Ok(TokenType::Float(float_proper)),
+            Err(_) => Err(GlangError::lexical(format!("Invalid float literal {:?}", numeral_string)).with_code("E0102"))
+        }
+    } else {
+        // Literals too large for i64 become big integers
+        match (numeral_string.parse::<i64>(), BigInt::parse(numeral_string)) {
+            (Ok(integer_proper), _) => Ok(TokenType::Integer(integer_proper)),
+            (Err(_), Some(big)) => Ok(TokenType::BigInteger(big)),
+            (Err(_), None) => Err(GlangError::lexical(format!("Invalid integer literal {:?}", numeral_string)).with_code("E0102"))
+        }
+    }
+}
+
+fn read_interpolation(source_datastream: &mut DataStream, brace: Span) -> Result<String, GlangError> {
+    // Reads up to the brace closing an embedded expression, skipping braces nested in the expression or its strings
+    let mut expression = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    loop {
+        if source_datastream.current_pos == source_datastream.characters.len() {
+            return Err(GlangError::lexical("Unterminated expression in string literal".to_string()).with_code("E0103").with_span(Span { length: 1, ..brace }))
+        }
+        let character = source_datastream.at();
+        match quote {
+            Some(open) if character == open => quote = None,
+            Some(_) if character == '\\' => {
+                // An escaped quote does not end a string nested in the expression
+                expression.push(character);
+                source_datastream.pop();
+                if source_datastream.current_pos < source_datastream.characters.len() {
+                    expression.push(source_datastream.at());
+                    source_datastream.pop();
+                }
+                continue
+            },
+            Some(_) => {},
+            None => match character {
+                '"' | '\'' => quote = Some(character),
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(expression),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        expression.push(character);
+        source_datastream.pop();
+    }
+}
+
+fn read_escape(source_datastream: &mut DataStream) -> Result<char, GlangError> {
+    // Reads the escape sequence at the backslash and returns the character it stands for
+    let start = source_datastream.position();
+    source_datastream.pop();
+    if source_datastream.current_pos == source_datastream.characters.len() {
+        return Err(GlangError::lexical("Unterminated string literal".to_string()).with_code("E0101").with_span(Span { length: 1, ..start }))
+    }
+    let character = source_datastream.at();
+    source_datastream.pop();
+    let escaped = match character {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' | '"' | '\'' | '{' | '}' => character,
+        'u' => {
+            // \u{...} takes 1 to 6 hex digits naming a Unicode scalar value
+            let mut digits = String::new();
+            if source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at() == '{' {
+                source_datastream.pop();
+                while source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at() != '}' && source_datastream.at() != '"' && digits.len() < 7 {
+                    digits.push(source_datastream.at());
+                    source_datastream.pop();
+                }
+            }
+            let valid = source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at() == '}' && (1..=6).contains(&digits.len());
+            let code_point = if valid { u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) } else { None };
+            match code_point {
+                Some(code_point) => {
+                    source_datastream.pop();
+                    code_point
+                },
+                None => return Err(GlangError::lexical(format!("Invalid unicode escape \\u{{{}}}", digits)).with_code("E0104")
+                    .with_span(source_datastream.span_from(&start))
+                    .with_help("unicode escapes look like `\\u{1F600}`, with 1 to 6 hex digits".to_string()))
+            }
+        },
+        other => return Err(GlangError::lexical(format!("Unknown escape sequence \\{}", other)).with_code("E0104")
+            .with_span(source_datastream.span_from(&start))
+            .with_help("use `\\\\` for a backslash, or a raw string such as `r\"C:\\path\"`".to_string()))
+    };
+    Ok(escaped)
+}
+
+fn read_string(source_datastream: &mut DataStream, start: Span, raw: bool) -> Result<TokenType, GlangError> {
+    // Leaves the closing quote for the main loop to consume. Text in braces is interpolated, except
+    // {{ and }} which stand for a brace and {} or {:spec} which are left as they are for format.
+    // Raw strings take every character as it is, with no escapes or interpolation
+    let quote = source_datastream.at();
+    let quote_count = |source_datastream: &DataStream| source_datastream.characters[source_datastream.current_pos..].iter().take(3).take_while(|character| **character == quote).count();
+    let triple = quote_count(source_datastream) == 3; // Triple quoted strings can span lines and contain lone quotes
+    source_datastream.pop();
+    if triple {
+        source_datastream.pop();
+        source_datastream.pop();
+    }
+    let mut parts: Vec<StringPart> = vec![];
+    let mut text = String::new();
+    loop {
+        if source_datastream.current_pos == source_datastream.characters.len() {
+            return Err(GlangError::lexical("Unterminated string literal".to_string()).with_code("E0101").with_span(Span { length: if triple { 3 } else { 1 }, ..start }))
+        }
+        let character = source_datastream.at();
+        let next = source_datastream.characters.get(source_datastream.current_pos + 1).copied();
+        if character == quote && (!triple || quote_count(source_datastream) == 3) {
+            if triple {
+                source_datastream.pop();
+                source_datastream.pop();
+            }
+            break
+        } else if raw {
+            text.push(character);
+            source_datastream.pop();
+        } else if character == '\\' {
+            text.push(read_escape(source_datastream)?);
+        } else if (character == '{' && next == Some('{')) || (character == '}' && next == Some('}')) {
+            text.push(character);
+            source_datastream.pop();
+            source_datastream.pop();
+        } else if character == '{' && !matches!(next, Some('}') | Some(':')) {
+            let brace = source_datastream.position();
+            source_datastream.pop();
+            let expression_start = source_datastream.position();
+            let expression = read_interpolation(source_datastream, brace)?;
+            source_datastream.pop();
+            if !text.is_empty() {
+                parts.push(StringPart::Text(std::mem::take(&mut text)));
+            }
+            parts.push(StringPart::Expression(tokenise_from(&expression, expression_start)?));
+        } else {
+            text.push(character);
+            source_datastream.pop();
+        }
+    }
+
+    if parts.is_empty() {
+        return Ok(TokenType::StringLiteral(text))
+    }
+    if !text.is_empty() {
+        parts.push(StringPart::Text(text));
+    }
+    Ok(TokenType::InterpolatedString(parts))
+}
+
+fn skip_block_comment(source_datastream: &mut DataStream, start: Span) -> Result<(), GlangError> {
+    // Block comments nest, so commenting out code that already has a /* */ in it works
+    let mut depth = 0;
+    loop {
+        if source_datastream.current_pos + 1 >= source_datastream.characters.len() {
+            return Err(GlangError::lexical("Unterminated block comment".to_string()).with_code("E0105").with_span(Span { length: 2, ..start }))
+        }
+        let pair = (source_datastream.at(), source_datastream.characters[source_datastream.current_pos + 1]);
+        if pair == ('/', '*') {
+            depth += 1;
+            source_datastream.pop();
+        } else if pair == ('*', '/') {
+            depth -= 1;
+            source_datastream.pop();
+            if depth == 0 {
+                source_datastream.pop();
+                return Ok(())
+            }
+        }
+        source_datastream.pop();
+    }
+}
+
+fn read_line_comment(source_datastream: &mut DataStream) -> String {
+    // Stops before the line break so that it still ends the statement
+    let mut comment = String::new();
+    while source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at() != '\n' {
+        comment.push(source_datastream.at());
+        source_datastream.pop();
+    }
+    comment
+}
+
+pub fn tokenise(source: String) -> Result<TokenStream, GlangError> {
+    tokenise_from(&source, Span { offset: 0, length: 0, line: 1, column: 1 })
+}
+
+fn tokenise_from(source: &str, position: Span) -> Result<TokenStream, GlangError> {
+    // Tokenises source found at the given position, so embedded expressions get spans into the whole script
+    let source_split: Vec<char> = source.chars().collect();
 
     let mut source_datastream = DataStream {
         characters: source_split,
-        current_pos: 0
+        current_pos: 0,
+        byte_offset: position.offset,
+        line: position.line,
+        column: position.column
     };
 
     let mut tokens: Vec<Token> = Vec::new();
+    let mut doc_comments: Vec<Token> = Vec::new();
     while source_datastream.current_pos < source_datastream.characters.len(){
         let mut is_alphanumeric = false;
-        let mut is_string_literal = false;
+        let start = source_datastream.position();
+        let token_count = tokens.len();
         if source_datastream.at() == '('{
-            tokens.push(Token {
-                token_type: TokenType::OpenBracket
-            });
+            tokens.push(Token::new(TokenType::OpenBracket));
         } else if source_datastream.at() == ')'{
-            tokens.push(Token {
-                token_type: TokenType::CloseBracket
-            });
+            tokens.push(Token::new(TokenType::CloseBracket));
         } else if source_datastream.at() == '{'{
-            tokens.push(Token {
-                token_type: TokenType::OpenCurlyBracket
-            }); 
+            tokens.push(Token::new(TokenType::OpenCurlyBracket)); 
         } else if source_datastream.at() == '}'{
-            tokens.push(Token {
-                token_type: TokenType::CloseCurlyBracket
-            });  
+            tokens.push(Token::new(TokenType::CloseCurlyBracket));  
+        } else if source_datastream.at() == '['{
+            tokens.push(Token::new(TokenType::OpenSquareBracket));
+        } else if source_datastream.at() == ']'{
+            tokens.push(Token::new(TokenType::CloseSquareBracket));
         } else if source_datastream.at() == '%'{
-            tokens.push(Token {
-                token_type: TokenType::Operator("%".to_string())
-            }); 
+            if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'=') {
+                tokens.push(Token::new(TokenType::Operator("%=".to_string())));
+                source_datastream.pop()
+            } else {
+                tokens.push(Token::new(TokenType::Operator("%".to_string())));
+            }
         } else if source_datastream.at() == '+'{
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("+=".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("+=".to_string())));
                 source_datastream.pop()
                 // source_split.remove(1);
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("+".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("+".to_string())));
             }
         } else if source_datastream.at() == '-'{
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("-=".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("-=".to_string())));
                 source_datastream.pop()
                 // source_split.remove(1);
-            } else if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1].is_ascii_digit() && !source_datastream.characters[source_datastream.current_pos-1].is_ascii_digit(){
+            } else if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1].is_ascii_digit() && (source_datastream.current_pos == 0 || !source_datastream.characters[source_datastream.current_pos-1].is_ascii_digit()){
                 // Negative numbers
                 source_datastream.pop();
                 let mut numeral: Vec<char> = Vec::new();
@@ -215,172 +472,162 @@ pub fn tokenise(source: String) -> TokenStream {
                     source_datastream.pop();
                 }
 
+                numeral.insert(0, '-');
                 let numeral_string: String = numeral.into_iter().collect();
 
-                if numeral_string.contains("."){
-                    let float_proper: f64 = numeral_string.parse::<f64>().unwrap();
-                    tokens.push(Token {
-                                    token_type: TokenType::Float(-1.0*float_proper)
-                                })
-                } else {
-                    let integer_proper: i64 = numeral_string.parse::<i64>().unwrap();
-                    tokens.push(Token {
-                        token_type: TokenType::Integer(-1*integer_proper)
-                    })
-                }
+                tokens.push(Token::new(parse_numeral(&numeral_string).map_err(|error| error.with_span(source_datastream.span_from(&start)))?));
                 
                 is_alphanumeric = true;
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("-".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("-".to_string())));
             }
         } else if source_datastream.at() == '*'{
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '=' {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("*=".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("*=".to_string())));
                 source_datastream.pop()
                 // source_split.remove(1);
+            } else if source_datastream.characters.len() - source_datastream.current_pos > 2 && source_datastream.characters[source_datastream.current_pos+1] == '*' && source_datastream.characters[source_datastream.current_pos+2] == '=' {
+                tokens.push(Token::new(TokenType::Operator("**=".to_string())));
+                source_datastream.pop();
+                source_datastream.pop()
             } else if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '*'{ 
-                tokens.push(Token {
-                    token_type: TokenType::Operator("**".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("**".to_string())));
                 source_datastream.pop()
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("*".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("*".to_string())));
+            }
+        } else if source_datastream.at() == '/' && source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'/') {
+            // Exactly three slashes make a doc comment, so a //// divider is still an ordinary comment
+            let comment = read_line_comment(&mut source_datastream);
+            if let Some(text) = comment.strip_prefix("///").filter(|text| !text.starts_with('/')) {
+                let text = text.strip_prefix(' ').unwrap_or(text).trim_end().to_string();
+                let span = source_datastream.span_from(&start);
+                match doc_comments.last_mut() {
+                    Some(Token { token_type: TokenType::DocComment(previous), span: previous_span }) if previous_span.line + previous.matches('\n').count() + 1 == start.line => {
+                        previous.push('\n');
+                        previous.push_str(&text);
+                        *previous_span = previous_span.to(&span);
+                    },
+                    _ => doc_comments.push(Token { token_type: TokenType::DocComment(text), span })
+                }
             }
+            is_alphanumeric = true;
+        } else if source_datastream.at() == '/' && source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'*') {
+            skip_block_comment(&mut source_datastream, start)?;
+            is_alphanumeric = true;
         } else if source_datastream.at() == '/'{
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '=' {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("/=".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("/=".to_string())));
                 source_datastream.pop()
                 // source_split.remove(1);
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("/".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("/".to_string())));
             }
         } else if source_datastream.at() == '='{
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("==".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("==".to_string())));
 
                 source_datastream.pop()
                 // source_split.remove(1);
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("=".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("=".to_string())));
             }
         } else if source_datastream.at() == '!' {
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("!=".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("!=".to_string())));
                 
                 source_datastream.pop()
                 // source_split.remove(1);
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("!".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("!".to_string())));
             }
         } else if source_datastream.at() == '>' {
-            if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
-                tokens.push(Token {
-                    token_type: TokenType::Operator(">=".to_string())
-                });
+            if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'>') {
+                if source_datastream.characters.get(source_datastream.current_pos + 2) == Some(&'=') {
+                    tokens.push(Token::new(TokenType::Operator(">>=".to_string())));
+                    source_datastream.pop();
+                } else {
+                    tokens.push(Token::new(TokenType::Operator(">>".to_string())));
+                }
+                source_datastream.pop()
+            } else if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
+                tokens.push(Token::new(TokenType::Operator(">=".to_string())));
                 
                 source_datastream.pop()
                 // source_split.remove(1);
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator(">".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator(">".to_string())));
             }
         } else if source_datastream.at() == '<' {
-            if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("<=".to_string())
-                });
+            if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'<') {
+                if source_datastream.characters.get(source_datastream.current_pos + 2) == Some(&'=') {
+                    tokens.push(Token::new(TokenType::Operator("<<=".to_string())));
+                    source_datastream.pop();
+                } else {
+                    tokens.push(Token::new(TokenType::Operator("<<".to_string())));
+                }
+                source_datastream.pop()
+            } else if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '='{
+                tokens.push(Token::new(TokenType::Operator("<=".to_string())));
                 
                 source_datastream.pop()
                 // source_split.remove(1);
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("<".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("<".to_string())));
             }
         } else if source_datastream.at() == ';' || source_datastream.at() == '\n'{
-            tokens.push(Token {
-                token_type: TokenType::EOL
-            });
-        } else if source_datastream.at() == '"'{
-            let mut string_literal: Vec<char> = Vec::new();
-
-            is_string_literal = true;
+            tokens.push(Token::new(TokenType::EOL));
+        } else if source_datastream.at() == '"' || source_datastream.at() == '\''{
+            tokens.push(Token::new(read_string(&mut source_datastream, start, false)?));
+        } else if source_datastream.at() == 'r' && matches!(source_datastream.characters.get(source_datastream.current_pos + 1), Some('"') | Some('\'')) {
             source_datastream.pop();
-
-            while source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at() != '"' {
-                string_literal.push(source_datastream.at());
-                source_datastream.pop();
-            }
-
-            let string_literal_string: String = string_literal.iter().collect();
-            
-            tokens.push(Token {
-                token_type: TokenType::StringLiteral(string_literal_string),
-            });
-        } else if source_datastream.at() == '\''{
-            let mut string_literal: Vec<char> = Vec::new();
-
-            is_string_literal = true;
-            source_datastream.pop();
-
-            while source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at() != '\'' {
-                string_literal.push(source_datastream.at());
-                source_datastream.pop();
-            }
-
-            let string_literal_string: String = string_literal.iter().collect();
-            
-            tokens.push(Token {
-                token_type: TokenType::StringLiteral(string_literal_string),
-            });
-
+            tokens.push(Token::new(read_string(&mut source_datastream, start, true)?));
         } else if source_datastream.at() == '&' {
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '&'{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("&&".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("&&".to_string())));
                 source_datastream.pop()
                 // source_split.remove(1);
+            } else if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'=') {
+                tokens.push(Token::new(TokenType::Operator("&=".to_string())));
+                source_datastream.pop()
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("&".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("&".to_string())));
             }
         } else if source_datastream.at() == '|' {
             if source_datastream.characters.len() - source_datastream.current_pos > 1 && source_datastream.characters[source_datastream.current_pos+1] == '|'{
-                tokens.push(Token {
-                    token_type: TokenType::Operator("||".to_string())
-                });
+                tokens.push(Token::new(TokenType::Operator("||".to_string())));
                 source_datastream.pop()
                 // source_split.remove(1);
+            } else if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'=') {
+                tokens.push(Token::new(TokenType::Operator("|=".to_string())));
+                source_datastream.pop()
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Operator("|".to_string())
-                });
-            } 
-        } else if source_datastream.at().is_ascii_alphabetic(){
+                tokens.push(Token::new(TokenType::Operator("|".to_string())));
+            }
+        } else if source_datastream.at() == '^' {
+            if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'=') {
+                tokens.push(Token::new(TokenType::Operator("^=".to_string())));
+                source_datastream.pop()
+            } else {
+                tokens.push(Token::new(TokenType::Operator("^".to_string())));
+            }
+        } else if source_datastream.at() == '~' {
+            // ~/ is integer division, // already starts a comment
+            if source_datastream.characters.get(source_datastream.current_pos + 1) == Some(&'/') {
+                if source_datastream.characters.get(source_datastream.current_pos + 2) == Some(&'=') {
+                    tokens.push(Token::new(TokenType::Operator("~/=".to_string())));
+                    source_datastream.pop();
+                } else {
+                    tokens.push(Token::new(TokenType::Operator("~/".to_string())));
+                }
+                source_datastream.pop()
+            } else {
+                tokens.push(Token::new(TokenType::Operator("~".to_string())));
+            }
+        } else if source_datastream.at().is_ascii_alphabetic() || source_datastream.at() == '_'{
             let mut identifier: Vec<char> = Vec::new();
 
-            while source_datastream.current_pos < source_datastream.characters.len() && source_datastream.at().is_alphanumeric(){
+            while source_datastream.current_pos < source_datastream.characters.len() && (source_datastream.at().is_alphanumeric() || source_datastream.at() == '_'){
                 identifier.push(source_datastream.at());
                 source_datastream.pop();
             }
@@ -391,18 +638,12 @@ pub fn tokenise(source: String) -> TokenStream {
 
             let token_type = TokenType::check_reserved_keywords(&identifier_string);
 
-            if token_type.is_some() {
-                tokens.push(Token {
-                    token_type: token_type.unwrap()
-                });
-            } else if source_datastream.characters[source_datastream.current_pos] == '(' {
-                tokens.push(Token {
-                    token_type: TokenType::FunctionCall(identifier_string)
-                });
+            if let Some(token_type) = token_type {
+                tokens.push(Token::new(token_type));
+            } else if source_datastream.characters.get(source_datastream.current_pos) == Some(&'(') {
+                tokens.push(Token::new(TokenType::FunctionCall(identifier_string)));
             } else {
-                tokens.push(Token {
-                    token_type: TokenType::Identifier(identifier_string)
-                });
+                tokens.push(Token::new(TokenType::Identifier(identifier_string)));
             }
 
 
@@ -415,43 +656,155 @@ pub fn tokenise(source: String) -> TokenStream {
 
             let numeral_string: String = numeral.into_iter().collect();
 
-            if numeral_string.contains("."){
-                let float_proper: f64 = numeral_string.parse::<f64>().unwrap();
-                tokens.push(Token {
-                                token_type: TokenType::Float(float_proper)
-                            })
-            } else {
-                let integer_proper: i64 = numeral_string.parse::<i64>().unwrap();
-                tokens.push(Token {
-                    token_type: TokenType::Integer(integer_proper)
-                })
-            }
+            tokens.push(Token::new(parse_numeral(&numeral_string).map_err(|error| error.with_span(source_datastream.span_from(&start)))?));
             
 
             is_alphanumeric = true;
             
         } else if source_datastream.at() == '.' {
-            tokens.push(Token {
-                token_type: TokenType::Punctuation(".".to_string())
-            });
+            tokens.push(Token::new(TokenType::Punctuation(".".to_string())));
         } else if source_datastream.at() == ',' {
-            tokens.push(Token {
-                token_type: TokenType::Punctuation(",".to_string())
-            });
-        } else if source_datastream.at() == ' ' || source_datastream.at() == '\n'{
+            tokens.push(Token::new(TokenType::Punctuation(",".to_string())));
+        } else if source_datastream.at() == ':' {
+            tokens.push(Token::new(TokenType::Punctuation(":".to_string())));
+        } else if source_datastream.at().is_whitespace() {
             // Do nothing
+        } else {
+            let error = GlangError::lexical(format!("Unexpected character {:?}", source_datastream.at())).with_span(Span { length: source_datastream.at().len_utf8(), ..start });
+            return Err(if source_datastream.at() == '#' { error.with_help("comments start with `//`".to_string()) } else { error })
         }
 
         if !is_alphanumeric{
             source_datastream.pop()
             // source_split.remove(0);
         }
-        
+
+        // Each pass reads at most one token, which spans every character consumed in that pass
+        if tokens.len() > token_count {
+            tokens.last_mut().unwrap().span = source_datastream.span_from(&start);
+        }
+
+    }
+    tokens.push(Token { token_type: TokenType::EOF, span: source_datastream.position() });
+
+    Ok(TokenStream {
+        tokens,
+        current_pos: 0,
+        doc_comments
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_token(source: &str) -> TokenType {
+        tokenise(source.to_string()).unwrap().tokens.remove(0).token_type
+    }
+
+    fn string(source: &str) -> String {
+        match first_token(source) {
+            TokenType::StringLiteral(text) => text,
+            other => panic!("expected a string literal, got {:?}", other)
+        }
+    }
+
+    fn error_code(source: &str) -> &'static str {
+        tokenise(source.to_string()).unwrap_err().code
     }
-    tokens.push(Token { token_type: TokenType::EOF });
 
-    TokenStream {
-        tokens: tokens,
-        current_pos: 0
+    #[test]
+    fn escapes_are_replaced() {
+        assert_eq!(string(r#""a\nb\tc\r\0""#), "a\nb\tc\r\0");
+        assert_eq!(string(r#""\\ \" \' \{ \}""#), "\\ \" ' { }");
+        assert_eq!(string(r#"'say "hi"'"#), "say \"hi\"");
+        assert_eq!(string(r#""\u{41}\u{e9}\u{1F600}""#), "Aé😀");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bad_escapes_are_errors() {
+        assert_eq!(error_code(r#""\q""#), "E0104");
+        assert_eq!(error_code(r#""\u41""#), "E0104");
+        assert_eq!(error_code(r#""\u{}""#), "E0104");
+        assert_eq!(error_code(r#""\u{1234567}""#), "E0104");
+        assert_eq!(error_code(r#""\u{110000}""#), "E0104");
+        assert_eq!(error_code(r#""\u{D800}""#), "E0104");
+    }
+
+    #[test]
+    fn raw_and_triple_quoted_strings() {
+        assert_eq!(string(r#"r"C:\path\{name}""#), r"C:\path\{name}");
+        assert_eq!(string(r#"r'\n'"#), r"\n");
+        assert_eq!(string("\"\"\"first \"quoted\"\nsecond\"\"\""), "first \"quoted\"\nsecond");
+        assert_eq!(string("'''it's'''"), "it's");
+        assert_eq!(string(r#"r"""\n "x" """"#), r#"\n "x" "#);
+        assert_eq!(string(r#""""""""#), "");
+        assert!(matches!(first_token("r"), TokenType::Identifier(ref name) if name == "r"));
+    }
+
+    fn parts(source: &str) -> Vec<String> {
+        // Text parts as they are and expressions as their token types, to compare against
+        match first_token(source) {
+            TokenType::InterpolatedString(parts) => parts.into_iter().map(|part| match part {
+                StringPart::Text(text) => text,
+                StringPart::Expression(expression) => format!("{:?}", expression.tokens.iter().map(|token| &token.token_type).collect::<Vec<_>>())
+            }).collect(),
+            other => panic!("expected an interpolated string, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn braces_interpolate_expressions() {
+        assert_eq!(parts(r#""a {x} b""#), ["a ", r#"[Identifier("x"), EOF]"#, " b"]);
+        assert_eq!(parts(r#""{x}{y + 1}""#), [r#"[Identifier("x"), EOF]"#, r#"[Identifier("y"), Operator("+"), Integer(1), EOF]"#]);
+        assert_eq!(parts(r#""{ {"}": 1}["}"] }""#).len(), 1);
+        assert_eq!(parts(r#""\\{x}""#)[0], "\\");
+        let TokenType::InterpolatedString(parts) = first_token("\"n = {n}\"") else { panic!("expected an interpolated string") };
+        let StringPart::Expression(expression) = &parts[1] else { panic!("expected an expression") };
+        assert_eq!(expression.tokens[0].span.column, 7);
+    }
+
+    #[test]
+    fn braces_that_do_not_interpolate() {
+        assert_eq!(string(r#""{{x}} }}""#), "{x} }");
+        assert_eq!(string(r#""{} {:>5} {:.2}""#), "{} {:>5} {:.2}");
+        assert_eq!(string(r#""\{x\}""#), "{x}");
+        assert_eq!(string(r#"r"{x}""#), "{x}");
+        assert_eq!(error_code(r#""{x""#), "E0103");
+        assert_eq!(error_code(r#""{"}"#), "E0103");
+        assert_eq!(error_code(r#""{x @ y}""#), "E0100");
+    }
+
+    #[test]
+    fn unterminated_strings_are_errors() {
+        assert_eq!(error_code("\"abc"), "E0101");
+        assert_eq!(error_code("\"abc\\"), "E0101");
+        assert_eq!(error_code("\"\"\"abc\"\""), "E0101");
+        assert_eq!(error_code("r\"abc"), "E0101");
+        let error = tokenise("let s = \"abc".to_string()).unwrap_err();
+        assert_eq!((error.span.unwrap().line, error.span.unwrap().column), (1, 9));
+    }
+
+    fn token_types(source: &str) -> Vec<String> {
+        tokenise(source.to_string()).unwrap().tokens.iter().map(|token| format!("{:?}", token.token_type)).collect()
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(token_types("let x = 1 // one {\nx"), token_types("let x = 1\nx"));
+        assert_eq!(token_types("1 /* a\nb */ + /**/ 2"), token_types("1 + 2"));
+        assert_eq!(token_types("/* outer /* inner */ still outer */ x"), token_types("x"));
+        assert_eq!(token_types("\"// not a comment\" 4 / 2"), ["StringLiteral(\"// not a comment\")", "Integer(4)", "Operator(\"/\")", "Integer(2)", "EOF"]);
+        assert_eq!(error_code("/* /* */ x"), "E0105");
+        assert_eq!(error_code("x # y"), "E0100");
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_line() {
+        let stream = tokenise("//// divider\n/// Adds one.\n///   Indented\nfunc f(x) {}\n\n/// Lone".to_string()).unwrap();
+        assert_eq!(stream.doc_comments.len(), 2);
+        assert!(matches!(stream.doc_comment_before(4).map(|token| token.token_type), Some(TokenType::DocComment(ref text)) if text == "Adds one.\n  Indented"));
+        assert!(stream.doc_comment_before(2).is_none());
+        assert!(stream.doc_comment_before(7).is_some());
+        assert_eq!(token_types("/// doc\nx"), token_types("\nx"));
+    }
+}
@@ -2,4 +2,16 @@ pub mod lexer;
 pub mod parser;
 pub mod eval;
 pub mod environment;
-pub mod run;
\ No newline at end of file
+pub mod builtins;
+pub mod error;
+pub mod diagnostics;
+pub mod run;
+pub mod interpreter;
+pub mod repl;
+pub mod userdata;
+pub mod bigint;
+
+pub use interpreter::Interpreter;
+pub use eval::{RuntimeVal, RuntimeValType};
+pub use error::GlangError;
+pub use userdata::{HostObject, UserData};
@@ -1,10 +1,14 @@
 use super::lexer;
+use super::error::GlangError;
+use std::cell::Cell;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone)]
 pub enum NodeType {
     Program,
     NumericLiteral,
     StringLiteral,
+    InterpolatedString,
     Boolean,
     Identifier,
     BinaryExpr(String),
@@ -18,8 +22,22 @@ pub enum NodeType {
     Else,
     ElseIf,
     Print,
+    NamedArgument,
     Function(String),
     FunctionCall(String),
+    AnonymousFunction,
+    Call,
+    Array,
+    Index,
+    IndexAssignment,
+    Map,
+    Struct(String),
+    StructInstance,
+    FieldAccess,
+    FieldAssignment,
+    CompoundAssignment(String),
+    MethodCall,
+    Return,
     EOL
 }
 impl NodeType {
@@ -37,176 +55,529 @@ impl NodeType {
             None
         }
     }
+    pub fn extract_fncall_identifier(&self) -> Option<&str> {
+        if let NodeType::FunctionCall(identifier) = self {
+            Some(identifier)
+        } else {
+            None
+        }
+    }
 }
 #[derive(Debug, Clone)]
 pub struct Node{ // Node
     pub node_type: NodeType,
     pub value: Option<lexer::Token>,
-    pub body: Vec<Node>
+    pub body: Vec<Node>,
+    pub span: lexer::Span
+}
+impl Node {
+    pub fn tree(&self) -> String {
+        // One node per line, indented by depth, with the token and location of each node
+        let mut output = String::new();
+        self.write_tree(&mut output, 0);
+        output
+    }
+
+    // The /// comment above a function declaration, for documentation tools
+    pub fn doc_comment(&self) -> Option<&str> {
+        match &self.value {
+            Some(lexer::Token { token_type: lexer::TokenType::DocComment(text), .. }) => Some(text),
+            _ => None
+        }
+    }
+
+    fn write_tree(&self, output: &mut String, depth: usize) {
+        output.push_str(&"  ".repeat(depth));
+        output.push_str(&format!("{:?}", self.node_type));
+        if let Some(token) = &self.value {
+            output.push_str(&format!(" {:?}", token.token_type));
+        }
+        if self.span != lexer::Span::default() {
+            output.push_str(&format!(" @ {}:{}", self.span.line, self.span.column));
+        }
+        output.push('\n');
+        for child in self.body.iter().filter(|child| !matches!(child.node_type, NodeType::EOL)) {
+            child.write_tree(output, depth + 1);
+        }
+    }
 }
+fn compound_operator(token_type: &lexer::TokenType) -> Option<&str> {
+    // Gives the binary operator of a compound assignment, e.g. + for +=
+    match token_type {
+        lexer::TokenType::Operator(op) if !matches!(op.as_str(), "=" | "==" | "!=" | "<=" | ">=") => op.strip_suffix('='),
+        _ => None
+    }
+}
+
+// Expressions and blocks nested deeper than this are a syntax error rather than overflowing the stack,
+// the parser recurses once or twice for every level
+pub const MAX_NESTING_DEPTH: usize = 100;
+
+thread_local! {
+    static NESTING_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// Counts one level of nesting for as long as the parser is inside it
+struct NestingGuard;
+impl NestingGuard {
+    fn enter(span: lexer::Span) -> Result<NestingGuard, GlangError> {
+        let depth = NESTING_DEPTH.with(|depth| {
+            depth.set(depth.get() + 1);
+            depth.get()
+        });
+        let guard = NestingGuard;
+        if depth > MAX_NESTING_DEPTH {
+            return Err(GlangError::syntax(format!("Code is nested too deeply, more than {} levels", MAX_NESTING_DEPTH)).with_code("E0204").with_span(span)
+                .with_help("move some of the inner expressions or blocks into variables or functions".to_string()))
+        }
+        Ok(guard)
+    }
+}
+impl Drop for NestingGuard {
+    fn drop(&mut self) {
+        NESTING_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
 impl Node { // Master node will ALWAYS be of type Program and will always have all tokens in tokens
-    fn parse_stmt(&mut self, tokens: &mut lexer::TokenStream) -> Node{
+    fn parse_stmt(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
         if matches!(&tokens.at().token_type, lexer::TokenType::Let){ // Declaration
             tokens.pop();
             if !matches!(tokens.at().token_type, lexer::TokenType::Identifier(_)){
-                panic!("Expected identifier after let")
+                Err(GlangError::syntax("Expected identifier after let".to_string()))
             } else {
-                let mut ret = Node {node_type: NodeType::Declaration, value: Some(tokens.at()), body: vec![]};
+                let mut ret = Node {node_type: NodeType::Declaration, value: Some(tokens.at()), body: vec![], span: tokens.at().span};
                 tokens.pop();
-                while !(matches!(&tokens.at().token_type, lexer::TokenType::EOL) || matches!(&tokens.at().token_type, lexer::TokenType::EOF)) {
-                    tokens.pop();
-                    ret.body.push(self.parse_expr(tokens));
-                    
+                if !matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "=") {
+                    return Err(GlangError::syntax(format!("Expected = after let, got: {:?}", tokens.at().token_type)).with_code("E0201").with_span(tokens.at().span)
+                        .with_help("variables are declared with a value, e.g. `let x = 0`".to_string()))
                 }
+                tokens.pop();
+                ret.body.push(self.parse_assigned_value(tokens)?);
+                ret.span = tokens.span_from(start);
                 
-                return ret 
+                Ok(ret)
+            }
+        } else if matches!(&tokens.at().token_type, lexer::TokenType::Function) && !matches!(tokens.tokens[tokens.current_pos + 1].token_type, lexer::TokenType::OpenBracket) {
+            self.parse_function_declaration(tokens)
+        } else if matches!(&tokens.at().token_type, lexer::TokenType::Struct) {
+            tokens.pop();
+            let struct_identifier = tokens.at();
+            if !matches!(struct_identifier.token_type, lexer::TokenType::Identifier(_)){
+                return Err(GlangError::syntax(format!("Expected identifier after struct, got: {:?}", struct_identifier.token_type)))
             }
-        } else if matches!(&tokens.at().token_type, lexer::TokenType::Function) {
             tokens.pop();
-            let fn_identifier = tokens.at().clone();
-            let fn_identifier_str = fn_identifier.token_type.extract_str_value().unwrap();
+            if !matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
+                return Err(GlangError::syntax(format!("Expected opening curly bracket after struct name, got: {:?}", tokens.at().token_type)).with_code("E0203"))
+            }
             tokens.pop();
-            if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                let mut body: Vec<Node> = vec![]; // Zeroeth item in body is input, next is scope
-                let mut declarations: Vec<Node> = vec![]; // Zeroeth item of body; just a bunch of declarations for the scope
-                while !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
+            let mut body: Vec<Node> = vec![]; // Field identifiers and method declarations, in any order
+            while !matches!(tokens.at().token_type, lexer::TokenType::CloseCurlyBracket) {
+                if matches!(tokens.at().token_type, lexer::TokenType::Identifier(_)) {
+                    body.push(Node {node_type: NodeType::Identifier, value: Some(tokens.at()), body: vec![], span: tokens.at().span});
                     tokens.pop();
-                    if matches!(tokens.at().token_type, lexer::TokenType::Punctuation(op) if op == ",".to_string()) || matches!(tokens.at().token_type, lexer::TokenType::CloseBracket){
-                        continue
-                    } else {
-                        declarations.push(Node {
-                            node_type: NodeType::Declaration,
-                            value: Some(tokens.at()), 
-                            body: vec![Node {
-                                node_type: NodeType::NumericLiteral,
-                                value: Some(lexer::Token { token_type: lexer::TokenType::Integer(0) }),
-                                body: vec![]
-                            }]
-                        });
-                    }
-                    // println!("{:?}",tokens);
+                } else if matches!(tokens.at().token_type, lexer::TokenType::Function) {
+                    body.push(self.parse_function_declaration(tokens)?);
+                } else if matches!(tokens.at().token_type, lexer::TokenType::EOL) || matches!(tokens.at().token_type, lexer::TokenType::Punctuation(ref pn) if pn == ",") {
+                    tokens.pop();
+                } else {
+                    return Err(GlangError::syntax(format!("Expected field or method in struct declaration, got: {:?}", tokens.at().token_type)))
+                }
+            }
+            tokens.pop();
+            let struct_identifier_str = struct_identifier.token_type.extract_str_value().unwrap();
+            Ok(Node {node_type: NodeType::Struct(struct_identifier_str.to_string()), value: None, body, span: tokens.span_from(start)})
+        } else if matches!(&tokens.at().token_type, lexer::TokenType::OpenCurlyBracket) {
+            self.parse_scope(tokens)
+        } else {
+            self.parse_expr(tokens)
+        }
+    }
+
+    fn parse_scope(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        let _nesting = NestingGuard::enter(tokens.at().span)?;
+        if !matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
+            return Err(GlangError::syntax(format!("Expected opening curly bracket, got: {:?}", tokens.at().token_type)).with_code("E0203"))
+        }
+        tokens.pop();
+        let mut body: Vec<Node> = vec![];
+        while !matches!(tokens.at().token_type, lexer::TokenType::CloseCurlyBracket){
+            body.push(self.parse_stmt(tokens)?);
+        }
+        tokens.pop();
+        Ok(Node {
+            node_type: NodeType::Scope,
+            value: None,
+            body,
+            span: tokens.span_from(start)
+        })
+    }
+
+    fn parse_function_declaration(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        let doc_comment = tokens.doc_comment_before(tokens.at().span.line); // A /// comment on the lines above documents the function
+        tokens.pop();
+        let fn_identifier = tokens.at();
+        let fn_identifier_str = match &fn_identifier.token_type {
+            lexer::TokenType::Identifier(identifier) | lexer::TokenType::FunctionCall(identifier) => identifier.to_string(),
+            other => return Err(GlangError::syntax(format!("Expected function name after func, got: {:?}", other)))
+        };
+        tokens.pop();
+        let body = self.parse_function_definition(tokens)?;
+        Ok(Node {node_type: NodeType::Function(fn_identifier_str), value: doc_comment, body, span: tokens.span_from(start)})
+    }
 
+    fn parse_function_definition(&mut self, tokens: &mut lexer::TokenStream) -> Result<Vec<Node>, GlangError>{
+        if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
+            let mut body: Vec<Node> = vec![]; // Parameter identifiers first, last item is scope
+            tokens.pop();
+            self.skip_eol(tokens);
+            while !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
+                if !matches!(tokens.at().token_type, lexer::TokenType::Identifier(_)) {
+                    return Err(GlangError::syntax(format!("Expected parameter name in function definition, got: {:?}", tokens.at().token_type)).with_span(tokens.at().span))
                 }
+                body.push(Node {node_type: NodeType::Identifier, value: Some(tokens.at()), body: vec![], span: tokens.at().span});
                 tokens.pop();
-                if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
-                    body.push(self.parse_expr(tokens));
-                    for node in declarations {
-                        body[0].body.insert(0,node); // Injecting declerations into scope, there's probably a way more efficient way of doing this
-                    }
-                    let ret = Node {node_type: NodeType::Function(fn_identifier_str.to_string()), value: None, body: body};
-                    return ret
-                } 
-                panic!()
+                self.parse_list_separator(tokens, lexer::TokenType::CloseBracket, "parameter list")?;
             }
-            panic!()
-        } else if matches!(&tokens.at().token_type, lexer::TokenType::FunctionCall(_)){
-            let op = &tokens.at().token_type;
-            let mut body: Vec<Node> = vec![]; // Body items are input values
             tokens.pop();
-            if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                while !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
-                    // println!("hi");
-                    tokens.pop();
-                    if matches!(tokens.at().token_type, lexer::TokenType::Punctuation(pn) if pn == ",".to_string()){
-                        tokens.pop();
-                    }
-                    body.push(self.parse_expr(tokens));
-                    // println!("{:?}",tokens);
-                }
+            if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
+                body.push(self.parse_scope(tokens)?);
+                return Ok(body)
+            } 
+            return Err(GlangError::syntax(format!("Expected function body, got: {:?}", tokens.at().token_type)))
+        }
+        Err(GlangError::syntax(format!("Expected parameter list, got: {:?}", tokens.at().token_type)))
+    }
+
+    fn parse_call_args(&mut self, tokens: &mut lexer::TokenStream) -> Result<Vec<Node>, GlangError>{
+        let mut body: Vec<Node> = vec![]; // Body items are input values
+        tokens.pop();
+        self.skip_eol(tokens);
+        while !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
+            if matches!(tokens.at().token_type, lexer::TokenType::Identifier(_))
+                && matches!(tokens.tokens[tokens.current_pos + 1].token_type, lexer::TokenType::Operator(ref op) if op == "=") {
+                return Err(GlangError::syntax("Function calls do not take named arguments".to_string()).with_code("E0201").with_span(tokens.at().span).with_help("only print takes `sep` and `end`".to_string()))
+            }
+            body.push(self.parse_expr(tokens)?);
+            self.parse_list_separator(tokens, lexer::TokenType::CloseBracket, "call arguments")?;
+        }
+        tokens.pop();
+        Ok(body)
+    }
+
+    fn parse_print_args(&mut self, tokens: &mut lexer::TokenStream) -> Result<Vec<Node>, GlangError>{
+        let mut body: Vec<Node> = vec![]; // Items are the values to print and any sep or end named arguments
+        tokens.pop();
+        self.skip_eol(tokens);
+        while !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
+            let is_named = matches!(tokens.at().token_type, lexer::TokenType::Identifier(ref name) if name == "sep" || name == "end")
+                && matches!(tokens.tokens[tokens.current_pos + 1].token_type, lexer::TokenType::Operator(ref op) if op == "=");
+            if is_named {
+                let name = tokens.at();
                 tokens.pop();
+                tokens.pop();
+                let value = self.parse_expr(tokens)?;
+                let span = name.span.to(&value.span);
+                body.push(Node {node_type: NodeType::NamedArgument, value: Some(name), body: vec![value], span});
+            } else {
+                body.push(self.parse_expr(tokens)?);
             }
-            
-            let ret = Node {node_type: NodeType::FunctionCall(op.extract_fncall_identifier().unwrap().to_string()), value: None, body: body};
-            ret
-        } else {
-            self.parse_expr(tokens)
+            self.parse_list_separator(tokens, lexer::TokenType::CloseBracket, "print")?;
         }
+        tokens.pop();
+        Ok(body)
     }
 
-    fn parse_expr(&mut self, tokens: &mut lexer::TokenStream) -> Node{
-        self.parse_comparative_expr(tokens)
+    fn parse_assigned_value(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        // The single expression after the = (or compound operator) of a declaration or assignment, which must end the statement
+        if matches!(tokens.at().token_type, lexer::TokenType::EOL | lexer::TokenType::EOF | lexer::TokenType::CloseCurlyBracket) {
+            return Err(GlangError::syntax(format!("Expected a value to assign, got: {:?}", tokens.at().token_type)).with_code("E0201").with_span(tokens.at().span))
+        }
+        let value = self.parse_expr(tokens)?;
+        if !matches!(tokens.at().token_type, lexer::TokenType::EOL | lexer::TokenType::EOF | lexer::TokenType::CloseCurlyBracket) {
+            return Err(GlangError::syntax(format!("Unexpected token after assigned value - expected end of statement, got: {:?}", tokens.at().token_type)).with_code("E0201").with_span(tokens.at().span))
+        }
+        Ok(value)
     }
 
-    fn parse_comparative_expr(&mut self, tokens: &mut lexer::TokenStream) -> Node{
-        let mut left: Node = self.parse_additive_expr(tokens);
-        while matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "==" || op == "!=" || op == ">" || op == "<" || op == ">=" || op == "<=" || op == "&&" || op == "||") {
-            let operator = tokens.at();
+    fn skip_eol(&mut self, tokens: &mut lexer::TokenStream){
+        // Line breaks are allowed between the items of a bracketed list
+        while matches!(tokens.at().token_type, lexer::TokenType::EOL) {
             tokens.pop();
-            let right = self.parse_additive_expr(tokens);
-            left = Node {
-                node_type: NodeType::BinaryExpr(operator.token_type.extract_operator().unwrap().to_string()),
-                value: None,
-                body: vec![left, right]
-            };   
         }
-        left
     }
 
-    fn parse_additive_expr(&mut self, tokens: &mut lexer::TokenStream) -> Node{
-        let mut left: Node = self.parse_multiplicative_expr(tokens);
-        // tokens.pop();
-        while matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "+" || op == "-"){
-            let operator = tokens.at();
+    fn parse_list_separator(&mut self, tokens: &mut lexer::TokenStream, closing: lexer::TokenType, context: &str) -> Result<(), GlangError>{
+        // Each item of a bracketed list is followed by a comma or by the closing bracket
+        self.skip_eol(tokens);
+        if matches!(tokens.at().token_type, lexer::TokenType::Punctuation(ref pn) if pn == ","){
             tokens.pop();
-            let right = self.parse_multiplicative_expr(tokens);
-            left = Node {
-                node_type: NodeType::BinaryExpr(operator.token_type.extract_operator().unwrap().to_string()),
-                value: None,
-                body: vec![left, right]
+            self.skip_eol(tokens);
+        } else if std::mem::discriminant(&tokens.at().token_type) != std::mem::discriminant(&closing) {
+            let bracket = match closing {
+                lexer::TokenType::CloseSquareBracket => "closing square bracket",
+                lexer::TokenType::CloseCurlyBracket => "closing curly bracket",
+                _ => "closing bracket"
             };
+            return Err(GlangError::syntax(format!("Unexpected token within {} - expected comma or {}, got: {:?}", context, bracket, tokens.at().token_type)).with_code("E0203").with_span(tokens.at().span))
         }
-        left
+        Ok(())
     }
-    
-    fn parse_multiplicative_expr(& mut self, tokens: &mut lexer::TokenStream) -> Node{
-        let mut left: Node = self.parse_power_expr(tokens);
-        // tokens.pop();
-        while matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "*" || op == "/" || op == "%"){
+
+    fn parse_function_call(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        let identifier = tokens.at().token_type.extract_fncall_identifier().unwrap().to_string();
+        let mut body: Vec<Node> = vec![];
+        tokens.pop();
+        if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
+            body = self.parse_call_args(tokens)?;
+        }
+
+        Ok(Node {node_type: NodeType::FunctionCall(identifier), value: None, body, span: tokens.span_from(start)})
+    }
+
+    fn parse_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let left = self.parse_logical_or_expr(tokens)?;
+        if matches!(left.node_type, NodeType::Index) && matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "="){
+            tokens.pop();
+            let value = self.parse_assigned_value(tokens)?;
+            let span = left.span.to(&value.span);
+            let mut body = left.body; // Zeroeth item is the array, next is the index, last is the assigned value
+            body.push(value);
+            return Ok(Node {node_type: NodeType::IndexAssignment, value: None, body, span})
+        } else if matches!(left.node_type, NodeType::FieldAccess) && matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "="){
+            tokens.pop();
+            let value = self.parse_assigned_value(tokens)?;
+            let span = left.span.to(&value.span);
+            let mut body = left.body; // Zeroeth item is the instance, next is the assigned value
+            body.push(value);
+            return Ok(Node {node_type: NodeType::FieldAssignment, value: left.value, body, span})
+        } else if matches!(left.node_type, NodeType::Identifier | NodeType::Index | NodeType::FieldAccess) && let Some(operator) = compound_operator(&tokens.at().token_type) {
+            tokens.pop();
+            let value = self.parse_assigned_value(tokens)?;
+            let span = left.span.to(&value.span);
+            // Zeroeth item is the target, next is the value it is combined with
+            return Ok(Node {node_type: NodeType::CompoundAssignment(operator.to_string()), value: None, body: vec![left, value], span})
+        }
+        Ok(left)
+    }
+
+    // Parses one left associative precedence level, each operand is parsed by the next tighter level
+    fn parse_binary_level(&mut self, tokens: &mut lexer::TokenStream, operators: &[&str], next: fn(&mut Node, &mut lexer::TokenStream) -> Result<Node, GlangError>) -> Result<Node, GlangError>{
+        let mut left: Node = next(self, tokens)?;
+        while matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if operators.contains(&op.as_str())) {
             let operator = tokens.at();
             tokens.pop();
-            let right = self.parse_power_expr(tokens);
+            self.expect_operand(tokens, &operator)?;
+            let right = next(self, tokens)?;
+            let span = left.span.to(&right.span);
             left = Node {
                 node_type: NodeType::BinaryExpr(operator.token_type.extract_operator().unwrap().to_string()),
                 value: None,
-                body: vec![left, right]
+                body: vec![left, right],
+                span
             };
         }
-        left
+        Ok(left)
+    }
 
+    fn expect_operand(&mut self, tokens: &mut lexer::TokenStream, operator: &lexer::Token) -> Result<(), GlangError>{
+        // A line break straight after an operator would otherwise be taken as an empty operand
+        if matches!(tokens.at().token_type, lexer::TokenType::EOL) {
+            return Err(GlangError::syntax(format!("Expected a value after {}, got: EOL", operator.token_type.extract_operator().unwrap())).with_code("E0201").with_span(operator.span))
+        }
+        Ok(())
     }
 
-    fn parse_power_expr(& mut self, tokens: &mut lexer::TokenStream) -> Node{
-        let mut left: Node = self.parse_primary_expr(tokens);
-        // tokens.pop();
-        while matches!(&tokens.at().token_type, lexer::TokenType::Operator(op) if op == "**"){
-            let operator = tokens.at();
+    // Precedence from loosest to tightest follows C: ||, &&, |, ^, &, equality, relational, shift, additive, multiplicative, power
+    fn parse_logical_or_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["||"], Node::parse_logical_and_expr)
+    }
+
+    fn parse_logical_and_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["&&"], Node::parse_bitwise_or_expr)
+    }
+
+    fn parse_bitwise_or_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["|"], Node::parse_bitwise_xor_expr)
+    }
+
+    fn parse_bitwise_xor_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["^"], Node::parse_bitwise_and_expr)
+    }
+
+    fn parse_bitwise_and_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["&"], Node::parse_equality_expr)
+    }
+
+    fn parse_equality_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["==", "!="], Node::parse_relational_expr)
+    }
+
+    fn parse_relational_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["<", "<=", ">", ">="], Node::parse_shift_expr)
+    }
+
+    fn parse_shift_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["<<", ">>"], Node::parse_additive_expr)
+    }
+
+    fn parse_additive_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["+", "-"], Node::parse_multiplicative_expr)
+    }
+
+    fn parse_multiplicative_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["*", "/", "~/", "%"], Node::parse_power_expr)
+    }
+
+    fn parse_power_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        self.parse_binary_level(tokens, &["**"], Node::parse_call_expr)
+    }
+
+    fn parse_call_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        let mut callee: Node = self.parse_primary_expr(tokens)?;
+        loop {
+            if matches!(&tokens.at().token_type, lexer::TokenType::OpenBracket){ // Calling the result of an expression, e.g. make()(1)
+                let mut body = vec![callee]; // Zeroeth item is the callee, the rest are input values
+                body.extend(self.parse_call_args(tokens)?);
+                callee = Node {node_type: NodeType::Call, value: None, body, span: tokens.span_from(start)};
+            } else if matches!(&tokens.at().token_type, lexer::TokenType::OpenSquareBracket){
+                tokens.pop();
+                let index = self.parse_expr(tokens)?;
+                if !matches!(tokens.at().token_type, lexer::TokenType::CloseSquareBracket){
+                    return Err(GlangError::syntax(format!("Unexpected token within index - expected closing square bracket, got: {:?}", tokens.at().token_type)).with_code("E0203"))
+                }
+                tokens.pop();
+                callee = Node {node_type: NodeType::Index, value: None, body: vec![callee, index], span: tokens.span_from(start)}; // Zeroeth item is the array, next is the index
+            } else if matches!(&tokens.at().token_type, lexer::TokenType::Punctuation(pn) if pn == "."){
+                tokens.pop();
+                let member = tokens.at();
+                if matches!(member.token_type, lexer::TokenType::FunctionCall(_)) {
+                    tokens.pop();
+                    let mut body = vec![callee]; // Zeroeth item is the instance, the rest are input values
+                    body.extend(self.parse_call_args(tokens)?);
+                    callee = Node {node_type: NodeType::MethodCall, value: Some(member), body, span: tokens.span_from(start)};
+                } else if matches!(member.token_type, lexer::TokenType::Identifier(_)) {
+                    tokens.pop();
+                    callee = Node {node_type: NodeType::FieldAccess, value: Some(member), body: vec![callee], span: tokens.span_from(start)};
+                } else {
+                    return Err(GlangError::syntax(format!("Expected field or method name after '.', got: {:?}", member.token_type)))
+                }
+            } else {
+                break
+            }
+        }
+        Ok(callee)
+    }
+
+    fn parse_array_literal(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        tokens.pop();
+        let mut body: Vec<Node> = vec![]; // Items are the array elements
+        self.skip_eol(tokens);
+        while !matches!(tokens.at().token_type, lexer::TokenType::CloseSquareBracket) {
+            body.push(self.parse_expr(tokens)?);
+            self.parse_list_separator(tokens, lexer::TokenType::CloseSquareBracket, "array")?;
+        }
+        tokens.pop();
+        Ok(Node {node_type: NodeType::Array, value: None, body, span: tokens.span_from(start)})
+    }
+
+    fn parse_map_literal(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        tokens.pop();
+        let mut body: Vec<Node> = vec![]; // Items alternate between key and value
+        self.skip_eol(tokens);
+        while !matches!(tokens.at().token_type, lexer::TokenType::CloseCurlyBracket) {
+            body.push(self.parse_expr(tokens)?);
+            if !matches!(tokens.at().token_type, lexer::TokenType::Punctuation(ref pn) if pn == ":"){
+                return Err(GlangError::syntax(format!("Unexpected token within map - expected colon after key, got: {:?}", tokens.at().token_type)))
+            }
             tokens.pop();
-            let right = self.parse_primary_expr(tokens);
-            left = Node {
-                node_type: NodeType::BinaryExpr(operator.token_type.extract_operator().unwrap().to_string()),
-                value: None,
-                body: vec![left, right]
-            };
+            self.skip_eol(tokens);
+            body.push(self.parse_expr(tokens)?);
+            self.parse_list_separator(tokens, lexer::TokenType::CloseCurlyBracket, "map")?;
         }
-        left
+        tokens.pop();
+        Ok(Node {node_type: NodeType::Map, value: None, body, span: tokens.span_from(start)})
+    }
 
+    fn parse_struct_instance(&mut self, tokens: &mut lexer::TokenStream, identifier: lexer::Token, start: usize) -> Result<Node, GlangError>{
+        tokens.pop();
+        let mut body: Vec<Node> = vec![]; // Items alternate between field identifier and value
+        self.skip_eol(tokens);
+        while !matches!(tokens.at().token_type, lexer::TokenType::CloseCurlyBracket) {
+            if !matches!(tokens.at().token_type, lexer::TokenType::Identifier(_)){
+                return Err(GlangError::syntax(format!("Expected field name in struct instance, got: {:?}", tokens.at().token_type)))
+            }
+            body.push(Node {node_type: NodeType::Identifier, value: Some(tokens.at()), body: vec![], span: tokens.at().span});
+            tokens.pop();
+            if !matches!(tokens.at().token_type, lexer::TokenType::Punctuation(ref pn) if pn == ":"){
+                return Err(GlangError::syntax(format!("Unexpected token within struct instance - expected colon after field name, got: {:?}", tokens.at().token_type)))
+            }
+            tokens.pop();
+            body.push(self.parse_expr(tokens)?);
+            self.parse_list_separator(tokens, lexer::TokenType::CloseCurlyBracket, "struct instance")?;
+        }
+        tokens.pop();
+        Ok(Node {node_type: NodeType::StructInstance, value: Some(identifier), body, span: tokens.span_from(start)})
+    }
+
+    fn parse_control_flow(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        let node = match tokens.at().token_type {
+            lexer::TokenType::If => {
+                tokens.pop();
+                let condition = self.parse_condition(tokens, "if")?;
+                let scope = self.parse_scope(tokens)?;
+                Node {node_type: NodeType::If, value: None, body: vec![condition, scope], span: tokens.span_from(start)} // Zeroeth item in body is condition, next is scope
+            },
+            lexer::TokenType::ElseIf => {
+                tokens.pop();
+                let condition = self.parse_condition(tokens, "elif")?;
+                let scope = self.parse_scope(tokens)?;
+                Node {node_type: NodeType::ElseIf, value: None, body: vec![condition, scope], span: tokens.span_from(start)} // Zeroeth item in body is condition, next is scope
+            },
+            lexer::TokenType::Else => {
+                tokens.pop();
+                if matches!(tokens.at().token_type, lexer::TokenType::If) {
+                    return Err(GlangError::syntax("Expected opening curly bracket after else, got: If".to_string()).with_code("E0203").with_help("did you mean `elif`?".to_string()))
+                }
+                let scope = self.parse_scope(tokens)?;
+                Node {node_type: NodeType::Else, value: None, body: vec![scope], span: tokens.span_from(start)} // Scope
+            },
+            lexer::TokenType::Loop => {
+                tokens.pop();
+                let condition = self.parse_condition(tokens, "loop")?;
+                let scope = self.parse_scope(tokens)?;
+                Node {node_type: NodeType::Loop, value: None, body: vec![condition, scope], span: tokens.span_from(start)} // Zeroeth item in body is condition, next is scope
+            },
+            _ => unreachable!()
+        };
+        Ok(node)
     }
 
-    fn parse_primary_expr(&mut self, tokens: &mut lexer::TokenStream) -> Node{
-        match &tokens.at().token_type{
-            lexer::TokenType::Integer(_) => {
-                let ret = Node { node_type: NodeType::NumericLiteral, value: Some(tokens.at()), body: vec![] };
+    fn parse_primary_expr(&mut self, tokens: &mut lexer::TokenStream) -> Result<Node, GlangError>{
+        let start = tokens.current_pos;
+        let _nesting = NestingGuard::enter(tokens.at().span)?;
+        let node = match &tokens.at().token_type{
+            lexer::TokenType::Integer(_) | lexer::TokenType::BigInteger(_) => {
+                let ret = Node { node_type: NodeType::NumericLiteral, value: Some(tokens.at()), body: vec![], span: tokens.at().span };
                 tokens.pop();
                 ret
             },
             lexer::TokenType::Float(_) => {
-                let ret = Node { node_type: NodeType::NumericLiteral, value: Some(tokens.at()), body: vec![] };
+                let ret = Node { node_type: NodeType::NumericLiteral, value: Some(tokens.at()), body: vec![], span: tokens.at().span };
                 tokens.pop();
                 ret
             },
             lexer::TokenType::OpenBracket => {
                 tokens.pop();
-                let parsed: Node = self.parse_comparative_expr(tokens);
+                let parsed: Node = self.parse_logical_or_expr(tokens)?;
                 // tokens.pop();
                 // println!("{:?}", parsed);
 
@@ -214,185 +585,152 @@ impl Node { // Master node will ALWAYS be of type Program and will always have a
                     tokens.pop();
                     parsed
                 } else {
-                    panic!("Unexpected token within brackets - expected closing bracket, got: {:?}",tokens.at().token_type)
-                }
-            },
-            lexer::TokenType::OpenCurlyBracket => {
-                tokens.pop();
-                let mut body: Vec<Node> = vec![];
-                while !matches!(tokens.at().token_type, lexer::TokenType::CloseCurlyBracket){
-                    body.push(self.parse_stmt(tokens));
-                }
-                tokens.pop();
-                Node {
-                    node_type: NodeType::Scope,
-                    value: None,
-                    body: body,
+                    return Err(GlangError::syntax(format!("Unexpected token within brackets - expected closing bracket, got: {:?}", tokens.at().token_type)).with_code("E0203"))
                 }
             },
+            lexer::TokenType::OpenSquareBracket => self.parse_array_literal(tokens)?,
+            lexer::TokenType::OpenCurlyBracket => self.parse_map_literal(tokens)?, // Scopes are only parsed as statements, so in an expression this is a map
             lexer::TokenType::Identifier(_) => {
                 let identifier = tokens.at();
-                let ret = Node {node_type: NodeType::Identifier, value: Some(identifier.clone()), body: vec![]};
+                let ret = Node {node_type: NodeType::Identifier, value: Some(identifier.clone()), body: vec![], span: identifier.span};
                 tokens.pop();
+                if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
+                    return self.parse_struct_instance(tokens, identifier, start)
+                }
                 if matches!(tokens.at().token_type, lexer::TokenType::Operator(val) if val == "="){ // Assignment
-                    // tokens.pop();
-                    let mut body: Vec<Node> = vec![];
-                    while !matches!(tokens.at().token_type, lexer::TokenType::EOF) && !matches!(tokens.at().token_type, lexer::TokenType::EOL) {
-                        tokens.pop();
-                        body.push(self.parse_expr(tokens));
-                    }
-                    let ret = Node {node_type: NodeType::Assignment, value: Some(identifier.clone()), body: body};
-                    return ret
+                    tokens.pop();
+                    let body = vec![self.parse_assigned_value(tokens)?];
+                    return Ok(Node {node_type: NodeType::Assignment, value: Some(identifier.clone()), body, span: tokens.span_from(start)})
                 }
                 ret
             },
-            lexer::TokenType::Operator(op) if op == "!" => {
+            lexer::TokenType::Operator(op) if op == "!" || op == "~" => {
+                let operator = op.clone();
+                let operator_token = tokens.at();
                 tokens.pop();
-                if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                    let mut body: Vec<Node> = vec![];
-                        while !matches!(tokens.at().token_type, lexer::TokenType::EOF) && !matches!(tokens.at().token_type, lexer::TokenType::EOL) && !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
-                            tokens.pop();
-                            body.push(self.parse_expr(tokens));
-                        }
-                    tokens.pop();
-                    let ret = Node {node_type: NodeType::UnaryExpr("!".to_string()), value: None, body: body};
-                    return ret
-                } else {
-                    let right = self.parse_expr(tokens);
-                    let body = vec![right];
-                    let ret = Node {node_type: NodeType::UnaryExpr("!".to_string()), value: None, body: body};
-                    return ret
-                }
-                // panic!();
+                self.expect_operand(tokens, &operator_token)?;
+                // Binds tighter than any binary operator, so !a && b is (!a) && b
+                let right = self.parse_call_expr(tokens)?;
+                Node {node_type: NodeType::UnaryExpr(operator), value: None, body: vec![right], span: tokens.span_from(start)}
             },
             lexer::TokenType::StringLiteral(_) => {
-                let ret = Node {node_type: NodeType::StringLiteral, value: Some(tokens.at()), body: vec![]};
+                let ret = Node {node_type: NodeType::StringLiteral, value: Some(tokens.at()), body: vec![], span: tokens.at().span};
                 tokens.pop();
                 ret
             },
-            lexer::TokenType::EOL => {
-                let ret = Node { node_type: NodeType::EOL, value: None, body: vec![] };
+            lexer::TokenType::InterpolatedString(_) => {
+                let ret = self.parse_interpolated_string(tokens.at())?;
                 tokens.pop();
                 ret
             },
-            lexer::TokenType::Boolean(_) => {
-                let ret = Node {node_type: NodeType::Boolean, value: Some(tokens.at()), body: vec![]};
+            lexer::TokenType::EOL => {
+                let ret = Node { node_type: NodeType::EOL, value: None, body: vec![], span: tokens.at().span };
                 tokens.pop();
                 ret
             },
-            lexer::TokenType::If => {
-                tokens.pop();
-                if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                    let mut body: Vec<Node> = vec![]; // Zeroeth item in body is condition, next is scope
-                    while !matches!(tokens.at().token_type, lexer::TokenType::EOF) && !matches!(tokens.at().token_type, lexer::TokenType::EOL) && !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
-                        tokens.pop();
-                        body.push(self.parse_expr(tokens));
-                    }
-                    tokens.pop();
-                    if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
-                        body.push(self.parse_expr(tokens));
-                        let ret = Node {node_type: NodeType::If, value: None, body: body};
-                        return ret
-                    } 
-                    panic!()
-                }
-                panic!()
-            },
-            lexer::TokenType::ElseIf => {
-                tokens.pop();
-                if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                    let mut body: Vec<Node> = vec![]; // Zeroeth item in body is condition, next is scope
-                    while !matches!(tokens.at().token_type, lexer::TokenType::EOF) && !matches!(tokens.at().token_type, lexer::TokenType::EOL) && !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
-                        tokens.pop();
-                        body.push(self.parse_expr(tokens));
-                    }
-                    tokens.pop();
-                    if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
-                        body.push(self.parse_expr(tokens));
-                        let ret = Node {node_type: NodeType::ElseIf, value: None, body: body};
-                        return ret
-                    } 
-                    panic!()
-                }
-                panic!()
-            },
-            lexer::TokenType::Else => {
-                tokens.pop();
-                let mut body: Vec<Node> = vec![]; // Scope
-                if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
-                    body.push(self.parse_expr(tokens));
-                    let ret = Node {node_type: NodeType::Else, value: None, body: body};
-                    return ret
-                } 
-                panic!()
-
-            },
-            lexer::TokenType::Loop => {
+            lexer::TokenType::Boolean(_) => {
+                let ret = Node {node_type: NodeType::Boolean, value: Some(tokens.at()), body: vec![], span: tokens.at().span};
                 tokens.pop();
-                if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                    let mut body: Vec<Node> = vec![]; // Zeroeth item in body is condition, next is scope
-                        while !matches!(tokens.at().token_type, lexer::TokenType::EOF) && !matches!(tokens.at().token_type, lexer::TokenType::EOL) && !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
-                            tokens.pop();
-                            body.push(self.parse_expr(tokens));
-                        }
-                    tokens.pop();
-                    if matches!(tokens.at().token_type, lexer::TokenType::OpenCurlyBracket){
-                        body.push(self.parse_expr(tokens));
-                        let ret = Node {node_type: NodeType::Loop, value: None, body: body};
-                        return ret
-                    } 
-                    panic!()
-                }
-                panic!()
+                ret
             },
+            lexer::TokenType::If | lexer::TokenType::ElseIf | lexer::TokenType::Else | lexer::TokenType::Loop => self.parse_control_flow(tokens)?,
             lexer::TokenType::Print => {
                 tokens.pop();
                 if matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
-                    let mut body: Vec<Node> = vec![]; // Item is what is to be printed
-                        while !matches!(tokens.at().token_type, lexer::TokenType::EOF) && !matches!(tokens.at().token_type, lexer::TokenType::EOL) && !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket) {
-                            tokens.pop();
-                            body.push(self.parse_expr(tokens));
-                        }
-                    tokens.pop();
-                    let ret = Node {node_type: NodeType::Print, value: None, body: body};
-                    return ret
+                    let body = self.parse_print_args(tokens)?;
+                    return Ok(Node {node_type: NodeType::Print, value: None, body, span: tokens.span_from(start)})
                 }
-                panic!()
+                return Err(GlangError::syntax(format!("Expected opening bracket after print, got: {:?}", tokens.at().token_type)).with_code("E0203").with_help("print takes its argument in brackets, e.g. `print(x)`".to_string()))
             }
             lexer::TokenType::Break => {
-                let ret = Node {node_type: NodeType::Break, value: None, body: vec![]};
+                let ret = Node {node_type: NodeType::Break, value: None, body: vec![], span: tokens.at().span};
                 tokens.pop();
                 ret
             }
-            _ => panic!("{:?}", tokens.at().token_type)
+            lexer::TokenType::Return => {
+                tokens.pop();
+                let mut body: Vec<Node> = vec![]; // Item is the returned value, empty for a bare return
+                if !matches!(tokens.at().token_type, lexer::TokenType::EOL | lexer::TokenType::EOF | lexer::TokenType::CloseCurlyBracket) {
+                    body.push(self.parse_expr(tokens)?);
+                }
+                Node {node_type: NodeType::Return, value: None, body, span: tokens.span_from(start)}
+            }
+            lexer::TokenType::FunctionCall(_) => {
+                self.parse_function_call(tokens)?
+            }
+            lexer::TokenType::Function => {
+                tokens.pop();
+                let body = self.parse_function_definition(tokens)?;
+                Node {node_type: NodeType::AnonymousFunction, value: None, body, span: tokens.span_from(start)}
+            }
+            lexer::TokenType::EOF => return Err(GlangError::syntax("Unexpected end of file".to_string()).with_code("E0202")),
+            _ => return Err(GlangError::syntax(format!("Unexpected token: {:?}", tokens.at().token_type)).with_code("E0201"))
+        };
+        Ok(node)
+    }
+
+    fn parse_interpolated_string(&mut self, token: lexer::Token) -> Result<Node, GlangError>{
+        let mut body: Vec<Node> = vec![]; // Items are string literals and embedded expressions, in order
+        let lexer::TokenType::InterpolatedString(parts) = token.token_type else { unreachable!() };
+        for part in parts {
+            match part {
+                lexer::StringPart::Text(text) => {
+                    let text_token = lexer::Token { token_type: lexer::TokenType::StringLiteral(text), span: token.span };
+                    body.push(Node {node_type: NodeType::StringLiteral, value: Some(text_token), body: vec![], span: token.span});
+                },
+                lexer::StringPart::Expression(mut expression_tokens) => {
+                    let expression = self.parse_expr(&mut expression_tokens).map_err(|error| error.with_span(expression_tokens.at().span))?;
+                    if !matches!(expression_tokens.at().token_type, lexer::TokenType::EOF) {
+                        return Err(GlangError::syntax(format!("Unexpected token in string interpolation - expected closing curly bracket, got: {:?}", expression_tokens.at().token_type)).with_code("E0201").with_span(expression_tokens.at().span))
+                    }
+                    body.push(expression);
+                }
+            }
+        }
+        Ok(Node {node_type: NodeType::InterpolatedString, value: None, body, span: token.span})
+    }
+
+    fn parse_condition(&mut self, tokens: &mut lexer::TokenStream, keyword: &str) -> Result<Node, GlangError>{
+        if !matches!(tokens.at().token_type, lexer::TokenType::OpenBracket){
+            return Err(GlangError::syntax(format!("Expected opening bracket after {}, got: {:?}", keyword, tokens.at().token_type)).with_code("E0203").with_help(format!("conditions are wrapped in brackets, e.g. `{} (x > 1) {{`", keyword)))
+        }
+        tokens.pop();
+        let condition = self.parse_expr(tokens)?;
+        if !matches!(tokens.at().token_type, lexer::TokenType::CloseBracket){
+            return Err(GlangError::syntax(format!("Unexpected token within {} condition - expected closing bracket, got: {:?}", keyword, tokens.at().token_type)).with_code("E0203"))
         }
-        
+        tokens.pop();
+        Ok(condition)
     }
 
-    fn generate_ast(&mut self, tokens: &mut lexer::TokenStream){
+
+    fn generate_ast(&mut self, tokens: &mut lexer::TokenStream) -> Result<(), GlangError>{
         while !matches!(tokens.at().token_type, lexer::TokenType::EOF) {
-            let parsed = self.parse_stmt(tokens);
+            // Syntax errors point at the token the parser stopped on
+            let parsed = self.parse_stmt(tokens).map_err(|error| error.with_span(tokens.at().span))?;
             self.body.push(parsed);
             // println!("{:?}",self.body);
             // tokens.pop();
         }
+        Ok(())
     }
 }
 
-pub fn generate_ast(source: String) -> Node {
+pub fn generate_ast(source: String) -> Result<Node, GlangError> {
 
     let mut program = Node{
         node_type: NodeType::Program,
         value: None,
-        body: vec![]
+        body: vec![],
+        span: lexer::Span::default()
     };
 
-    let mut tokens = lexer::tokenise(source);
+    let mut tokens = lexer::tokenise(source)?;
 
     // println!("{:?}",tokens);
 
-    program.generate_ast(&mut tokens);
+    program.generate_ast(&mut tokens)?;
 
-    program
+    Ok(program)
 
 }
\ No newline at end of file
@@ -0,0 +1,201 @@
+use super::eval;
+use super::environment;
+use super::parser;
+use super::lexer;
+use super::diagnostics;
+use super::error::GlangError;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+:env          list the variables and structs defined so far
+:ast <code>   show the syntax tree of some code without running it
+:history      list previous inputs
+:!<n>         run input number n from :history again
+:reset        forget every variable, function and struct
+:help         show this message
+:quit         leave the REPL (Ctrl-D works too)";
+
+fn bracket_depth(source: &str) -> i64 {
+    // Counts with the lexer, so brackets inside strings and comments are ignored the same way the interpreter ignores them.
+    // An unterminated string or block comment is still open, any other lexical error is left for evaluation to report
+    match lexer::tokenise(source.to_string()) {
+        Ok(stream) => stream.tokens.iter().map(|token| match token.token_type {
+            lexer::TokenType::OpenBracket | lexer::TokenType::OpenCurlyBracket | lexer::TokenType::OpenSquareBracket => 1,
+            lexer::TokenType::CloseBracket | lexer::TokenType::CloseCurlyBracket | lexer::TokenType::CloseSquareBracket => -1,
+            _ => 0
+        }).sum(),
+        Err(error) if matches!(error.code, "E0101" | "E0105") => 1,
+        Err(_) => 0
+    }
+}
+
+fn is_expression(node: &parser::Node) -> bool {
+    matches!(node.node_type,
+        parser::NodeType::NumericLiteral | parser::NodeType::StringLiteral | parser::NodeType::InterpolatedString | parser::NodeType::Boolean | parser::NodeType::Identifier |
+        parser::NodeType::BinaryExpr(_) | parser::NodeType::UnaryExpr(_) | parser::NodeType::FunctionCall(_) | parser::NodeType::Call |
+        parser::NodeType::AnonymousFunction | parser::NodeType::Array | parser::NodeType::Index | parser::NodeType::Map |
+        parser::NodeType::StructInstance | parser::NodeType::FieldAccess | parser::NodeType::MethodCall)
+}
+
+fn evaluate(source: &str, env: Rc<RefCell<environment::Environment>>) -> Result<Option<eval::RuntimeVal>, GlangError> {
+    let ast = parser::generate_ast(source.to_string())?;
+    let value = eval::eval_program(&ast, env)?;
+
+    // Only a trailing bare expression is echoed, so declarations, assignments and prints stay quiet
+    let last_statement = ast.body.iter().rev().find(|node| !matches!(node.node_type, parser::NodeType::EOL));
+    if last_statement.is_some_and(is_expression) && !matches!(value.runtime_val_type, eval::RuntimeValType::Null) {
+        Ok(Some(value))
+    } else {
+        Ok(None)
+    }
+}
+
+fn print_env(env: Rc<RefCell<environment::Environment>>) {
+    let env_rc = env.borrow();
+    for struct_ in env_rc.structs.iter() {
+        println!("struct {} {{{}}}", struct_.name, struct_.fields.join(", "));
+    }
+    for variable in env_rc.variables.iter() {
+        println!("{} = {}", variable.name, variable.value.repr());
+    }
+}
+
+fn run_command(command: &str, history: &[String], env: &mut Rc<RefCell<environment::Environment>>) -> bool {
+    // Returns false when the REPL should stop
+    let (name, argument) = match command.split_once(' ') {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, "")
+    };
+    match name {
+        ":env" => print_env(env.clone()),
+        ":ast" => match parser::generate_ast(argument.to_string()) {
+            Ok(ast) => print!("{}", ast.tree()),
+            Err(error) => eprint!("{}", diagnostics::render(&error, argument, "<repl>", diagnostics::colour_enabled()))
+        },
+        ":history" => {
+            for (number, entry) in history.iter().enumerate() {
+                println!("{:>4}  {}", number + 1, entry.trim_end().replace('\n', "\n      "));
+            }
+        },
+        ":reset" => {
+            *env = environment::new_env(None);
+            println!("Environment reset");
+        },
+        ":help" => println!("{}", HELP),
+        ":quit" | ":q" => return false,
+        _ => eprintln!("Unknown command {}, try :help", name)
+    }
+    true
+}
+
+pub fn start() {
+    println!("glang {} - type :help for commands", env!("CARGO_PKG_VERSION"));
+    let mut env = environment::new_env(None);
+    let mut history: Vec<String> = vec![];
+    let mut buffer = String::new();
+    let mut lines = io::stdin().lock().lines();
+
+    loop {
+        print!("{}", if buffer.is_empty() { ">> " } else { ".. " });
+        io::stdout().flush().unwrap();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break // End of input
+        };
+
+        // A recalled entry is echoed and then handled as if it had been typed, so history records it rather than :!n
+        let line = match line.trim().strip_prefix(":!") {
+            Some(number) if buffer.is_empty() => match number.trim().parse::<usize>().ok().and_then(|number| history.get(number.wrapping_sub(1))) {
+                Some(entry) => {
+                    println!("{}", entry.trim_end());
+                    entry.trim_end().to_string()
+                },
+                None => {
+                    eprintln!("No history entry {}, :history lists them", number.trim());
+                    continue
+                }
+            },
+            _ => line
+        };
+
+        if buffer.is_empty() && line.trim_start().starts_with(':') {
+            history.push(line.trim().to_string());
+            if !run_command(line.trim(), &history, &mut env) {
+                break
+            }
+            continue
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+        // Keep reading while a block or literal is still open
+        if bracket_depth(&buffer) > 0 {
+            continue
+        }
+
+        let source = std::mem::take(&mut buffer);
+        if source.trim().is_empty() {
+            continue
+        }
+        history.push(source.clone());
+
+        // Errors are reported and the session carries on with whatever state was reached
+        match evaluate(&source, env.clone()) {
+            Ok(Some(value)) => println!("{}", value.repr()),
+            Ok(None) => {},
+            Err(error) => eprint!("{}", diagnostics::render(&error, &source, "<repl>", diagnostics::colour_enabled()))
+        }
+    }
+    println!();
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracket_depth_counts_open_brackets() {
+        assert_eq!(bracket_depth("func f() {\n"), 1);
+        assert_eq!(bracket_depth("let a = [1, (2\n"), 2);
+        assert_eq!(bracket_depth("if (x) { print(x) }\n"), 0);
+        assert_eq!(bracket_depth("}\n"), -1);
+    }
+
+    #[test]
+    fn bracket_depth_ignores_strings_and_comments() {
+        assert_eq!(bracket_depth("print(\"{{ ( [\")\n"), 0);
+        assert_eq!(bracket_depth("let x = 1 // {\n"), 0);
+        assert_eq!(bracket_depth("/* { */\n"), 0);
+        assert_eq!(bracket_depth("/* /* { */ ( */\n"), 0);
+        assert_eq!(bracket_depth("print(r\"C:\\\")\n"), 0);
+    }
+
+    #[test]
+    fn bracket_depth_keeps_unterminated_literals_open() {
+        assert!(bracket_depth("let s = \"\"\"first line\n") > 0);
+        assert!(bracket_depth("/* still a comment\n") > 0);
+        assert!(bracket_depth("/* /* */ nested\n") > 0);
+    }
+
+    #[test]
+    fn bracket_depth_leaves_other_lexical_errors_to_evaluation() {
+        assert_eq!(bracket_depth("print(\"\\q\")\n"), 0);
+    }
+
+    #[test]
+    fn evaluate_echoes_only_a_trailing_expression() {
+        let env = environment::new_env(None);
+        let echoed = |source: &str| evaluate(source, env.clone()).unwrap().map(|value| value.repr());
+        assert_eq!(echoed("let x = 40"), None);
+        assert_eq!(echoed("x + 2"), Some("42".to_string()));
+        assert_eq!(echoed("x = 1\n"), None);
+        assert_eq!(echoed("\"text\"\n\n"), Some("\"text\"".to_string()));
+        assert_eq!(echoed("func f() { return }"), None);
+        assert_eq!(echoed("f()"), None); // Null is not echoed
+        assert_eq!(echoed("[x, {\"k\": x}]"), Some("[1, {\"k\": 1}]".to_string()));
+        assert_eq!(echoed("if (x == 1) { 5 }"), None);
+        assert_eq!(evaluate("x +", env.clone()).unwrap_err().code, "E0202");
+    }
+}
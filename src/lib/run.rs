@@ -1,13 +1,7 @@
 use super::eval;
-use super::environment;
-use super::parser;
-use std::rc::Rc;
-use std::cell::RefCell;
+use super::error::GlangError;
+use super::interpreter::Interpreter;
 
-pub fn run_script(source: String) -> eval::RuntimeVal{
-    let ast = parser::generate_ast(source);
-    // println!("{:?}", ast);
-    let mut environment = Rc::new(RefCell::new(environment::Environment {parent: None, variables: vec![], functions: vec![]}));
-    let evaluate = eval::eval_program(&ast, environment);
-    evaluate
+pub fn run_script(source: String) -> Result<eval::RuntimeVal, GlangError>{
+    Interpreter::new().eval(&source)
 }
\ No newline at end of file
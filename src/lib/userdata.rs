@@ -0,0 +1,67 @@
+use super::eval;
+use super::error::GlangError;
+use std::any::Any;
+use std::fmt;
+use std::rc::Rc;
+
+// Implemented by host types that scripts can hold as userdata. Everything is optional, an object
+// with no fields or methods can still be passed around and handed back to native functions.
+// Methods take &self because the value is shared, use a RefCell for state that scripts can change
+pub trait HostObject: Any {
+    // Shown in error messages and by the default display
+    fn type_name(&self) -> &str;
+
+    fn get_field(&self, name: &str) -> Result<eval::RuntimeVal, GlangError> {
+        Err(GlangError::runtime(format!("{} has no field {:?}", self.type_name(), name)).with_code("E0307"))
+    }
+
+    fn set_field(&self, name: &str, _value: eval::RuntimeVal) -> Result<(), GlangError> {
+        Err(GlangError::runtime(format!("Cannot assign field {:?} of {}", name, self.type_name())).with_code("E0307"))
+    }
+
+    fn call_method(&self, name: &str, _arguments: &[eval::RuntimeVal]) -> Result<eval::RuntimeVal, GlangError> {
+        Err(GlangError::runtime(format!("{} has no method {:?}", self.type_name(), name)).with_code("E0307"))
+    }
+
+    // Used by print and the REPL
+    fn display(&self) -> String {
+        format!("<{}>", self.type_name())
+    }
+
+    // Called by == when both sides are userdata and are not the same object, `other` can be downcast
+    // to check its type. By default only the same object is equal to itself
+    fn equals(&self, _other: &dyn Any) -> bool {
+        false
+    }
+}
+
+// An opaque handle to a host object, shared by reference like arrays and struct instances
+#[derive(Clone)]
+pub struct UserData {
+    pub type_name: String,
+    pub object: Rc<dyn Any>,
+    host: Rc<dyn HostObject> // Same allocation as object, seen through the host trait
+}
+impl UserData {
+    pub fn new<T: HostObject>(object: T) -> UserData {
+        let host = Rc::new(object);
+        UserData { type_name: host.type_name().to_string(), object: host.clone(), host }
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.object.downcast_ref::<T>()
+    }
+
+    pub fn host(&self) -> &dyn HostObject {
+        self.host.as_ref()
+    }
+
+    pub fn equals(&self, other: &UserData) -> bool {
+        Rc::ptr_eq(&self.object, &other.object) || self.host.equals(other.object.as_ref())
+    }
+}
+impl fmt::Debug for UserData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserData").field("type_name", &self.type_name).finish_non_exhaustive()
+    }
+}
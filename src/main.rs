@@ -1,19 +1,174 @@
-mod lib;
 use std::env;
 use std::fs;
+use std::io::{self, Read};
+use std::process;
+use std::thread;
 
+// Exit codes
+const SCRIPT_ERROR: i32 = 1; // The script failed to lex, parse or run
+const USAGE_ERROR: i32 = 2; // Bad arguments or an unreadable file
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    // println!("{:?}",args);
+// Function calls recurse on the Rust stack, so the interpreter runs on a thread big enough for a call depth
+// limit well above the default even in debug builds
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+const MAX_CALL_DEPTH: usize = 1000;
+
+const USAGE: &str = "\
+Usage:
+    glang                      start the REPL
+    glang <file>               run a script, same as glang run <file>
+    glang run <file>           run a script
+    glang check <file>...      parse scripts without running them and report any errors
+    glang tokens <file>        print the tokens of a script
+    glang ast <file>           print the syntax tree of a script
+    glang -e <code>            run code given on the command line
+
+Use - as the file to read the script from stdin.
+
+Options:
+    -h, --help                 show this message
+    -V, --version              show the version
 
-    let mut source: Option<String> = None;
+Exit status is 0 on success, 1 if a script has an error and 2 for bad arguments or unreadable files.";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(USAGE_ERROR);
+}
+
+fn read_source(file_path: &str) -> String {
+    let source = if file_path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(file_path)
+    };
+    match source {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", file_path, error);
+            process::exit(USAGE_ERROR);
+        }
+    }
+}
+
+fn display_name(file_path: &str) -> &str {
+    if file_path == "-" { "<stdin>" } else { file_path }
+}
+
+fn report(error: &glang::error::GlangError, source: &str, file_name: &str) {
+    eprint!("{}", glang::diagnostics::render(error, source, file_name, glang::diagnostics::colour_enabled()));
+}
+
+fn run(source: String, file_name: &str) -> i32 {
+    match glang::run::run_script(source.clone()) {
+        Ok(_) => 0,
+        Err(error) => {
+            report(&error, &source, file_name);
+            SCRIPT_ERROR
+        }
+    }
+}
 
-    if args.len() == 2 {
-        let file_path = &args[1];
-        source = Some(fs::read_to_string(file_path).unwrap()); 
+fn check(file_paths: &[String]) -> i32 {
+    // Every file is checked, so one run reports the errors in all of them
+    let mut status = 0;
+    for file_path in file_paths {
+        let source = read_source(file_path);
+        match glang::parser::generate_ast(source.clone()) {
+            Ok(_) => println!("{}: ok", display_name(file_path)),
+            Err(error) => {
+                report(&error, &source, display_name(file_path));
+                status = SCRIPT_ERROR;
+            }
+        }
     }
-    
-    let out = lib::run::run_script(source.unwrap());
-    // println!("{:?}", out);
+    status
+}
+
+fn tokens(file_path: &str) -> i32 {
+    let source = read_source(file_path);
+    match glang::lexer::tokenise(source.clone()) {
+        Ok(token_stream) => {
+            for token in token_stream.tokens.iter() {
+                println!("{:>4}:{:<4} {:?}", token.span.line, token.span.column, token.token_type);
+            }
+            0
+        },
+        Err(error) => {
+            report(&error, &source, display_name(file_path));
+            SCRIPT_ERROR
+        }
+    }
+}
+
+fn ast(file_path: &str) -> i32 {
+    let source = read_source(file_path);
+    match glang::parser::generate_ast(source.clone()) {
+        Ok(ast) => {
+            print!("{}", ast.tree());
+            0
+        },
+        Err(error) => {
+            report(&error, &source, display_name(file_path));
+            SCRIPT_ERROR
+        }
+    }
+}
+
+fn single_file<'a>(command: &str, arguments: &'a [String]) -> &'a str {
+    match arguments {
+        [file_path] => file_path,
+        [] => usage_error(&format!("{} expects a file", command)),
+        _ => usage_error(&format!("{} expects a single file", command))
+    }
+}
+
+fn dispatch(arguments: &[String]) -> i32 {
+    match arguments.first().map(String::as_str) {
+        None => {
+            glang::repl::start();
+            0
+        },
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            0
+        },
+        Some("-V") | Some("--version") => {
+            println!("glang {}", env!("CARGO_PKG_VERSION"));
+            0
+        },
+        Some("-e") => match &arguments[1..] {
+            [code] => run(code.to_string(), "<command line>"),
+            _ => usage_error("-e expects the code to run as a single argument")
+        },
+        Some("run") => {
+            let file_path = single_file("run", &arguments[1..]);
+            run(read_source(file_path), display_name(file_path))
+        },
+        Some("check") => {
+            if arguments.len() == 1 {
+                usage_error("check expects at least one file")
+            }
+            check(&arguments[1..])
+        },
+        Some("tokens") => tokens(single_file("tokens", &arguments[1..])),
+        Some("ast") => ast(single_file("ast", &arguments[1..])),
+        Some(option) if option.starts_with('-') && option != "-" => usage_error(&format!("Unknown option {}", option)),
+        Some(_) => {
+            // A bare file path runs the script
+            let file_path = single_file("glang", arguments);
+            run(read_source(file_path), display_name(file_path))
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let interpreter = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        glang::eval::set_max_call_depth(MAX_CALL_DEPTH);
+        dispatch(&args[1..])
+    }).expect("could not start the interpreter thread");
+    let status = interpreter.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    process::exit(status);
 }
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{error_code, output};
+
+#[test]
+fn array_literals_and_indexing() {
+    let source = "
+        let a = [1, 2, 3]
+        a[1] = 10
+        push(a, 4)
+        insert(a, 0, 7)
+        print(a, len(a), a[4])
+        let grid = [[1, 2], [3, 4]]
+        grid[1][0] = 9
+        print(grid)
+    ";
+    assert_eq!(output(source).unwrap(), "[7, 1, 10, 3, 4] 5 4\n[[1, 2], [9, 4]]\n");
+}
+
+#[test]
+fn array_items_may_span_lines_and_end_with_a_comma() {
+    assert_eq!(output("print([\n    1,\n    2\n    , 3,\n])").unwrap(), "[1, 2, 3]\n");
+    assert_eq!(output("print([], [\n])").unwrap(), "[] []\n");
+}
+
+#[test]
+fn array_items_must_be_separated_by_commas() {
+    assert_eq!(error_code("let n = 5\nprint(len([n -1]))"), "E0203");
+    assert_eq!(error_code("print([1 2])"), "E0203");
+    assert_eq!(error_code("print([1\n2])"), "E0203");
+    assert_eq!(error_code("print([1, , 2])"), "E0201");
+    assert_eq!(output("let n = 5\nprint([n - 1, -1])").unwrap(), "[4, -1]\n");
+}
+
+#[test]
+fn out_of_range_indices_report_the_length() {
+    let message = |source: &str| output(source).unwrap_err().message;
+    assert_eq!(message("print([1, 2, 3][3])"), "Index out of bounds - index 3 with length 3");
+    assert_eq!(message("print(slice([1, 2, 3], 0, 4))"), "Index out of bounds - index 4 with length 3");
+    assert_eq!(message("print(slice(\"abc\", -1, 2))"), "Index out of bounds - index -1 with length 3");
+    assert_eq!(message("insert([1], 2, 0)"), "Index out of bounds - index 2 with length 1");
+    assert_eq!(output("let a = [1, 2, 3]\ninsert(a, 3, 4)\nprint(slice(a, 0, 4), slice(\"abc\", 3, 3))").unwrap(), "[1, 2, 3, 4] \n");
+}
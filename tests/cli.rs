@@ -0,0 +1,107 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn glang(arguments: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_glang"))
+        .args(arguments)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("glang runs");
+    // Commands that do not read stdin may exit before it is written, which is not an error here
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// A script written to a file of its own in the temp directory, named after the test so runs in parallel do not clash.
+// The file is removed when the test is done with it
+struct Script(PathBuf);
+impl Script {
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+impl Drop for Script {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn script(name: &str, source: &str) -> Script {
+    let path = std::env::temp_dir().join(format!("glang-cli-{}-{}.glang", std::process::id(), name));
+    fs::write(&path, source).unwrap();
+    Script(path)
+}
+
+#[test]
+fn runs_code_from_files_arguments_and_stdin() {
+    let file = script("run", "print(1 + 1)");
+    for arguments in [vec!["run", file.path()], vec![file.path()], vec!["-e", "print(1 + 1)"], vec!["-"]] {
+        let output = glang(&arguments, "print(1 + 1)");
+        assert_eq!((output.status.code(), stdout(&output)), (Some(0), "2\n".to_string()), "{:?}", arguments);
+    }
+}
+
+#[test]
+fn script_errors_exit_with_1_and_a_diagnostic() {
+    let file = script("error", "let x = 1\nlet y = x / 0");
+    let output = glang(&["run", file.path()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("Runtime error[E0303]: Division by zero\n"), "{}", stderr(&output));
+    assert!(stderr(&output).contains(&format!("{}:2:9", file.path())));
+    let output = glang(&["-"], "print(");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("<stdin>"));
+}
+
+#[test]
+fn check_parses_every_file_without_running_them() {
+    let good = script("check-good", "print(\"should not run\")");
+    let bad = script("check-bad", "let = 1");
+    let output = glang(&["check", bad.path(), good.path()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("{}: ok\n", good.path()));
+    assert!(stderr(&output).contains(&format!("{}:1:5", bad.path())), "{}", stderr(&output));
+}
+
+#[test]
+fn tokens_and_ast_describe_the_script() {
+    let file = script("inspect", "let x = 1");
+    let output = glang(&["tokens", file.path()], "");
+    assert_eq!(stdout(&output).lines().next(), Some("   1:1    Let"));
+    assert!(stdout(&output).contains("Integer(1)"));
+    let output = glang(&["ast", file.path()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("Declaration"), "{}", stdout(&output));
+}
+
+#[test]
+fn bad_arguments_exit_with_2() {
+    for arguments in [vec!["--nope"], vec!["run"], vec!["run", "a", "b"], vec!["check"], vec!["-e"], vec!["/no/such/file.glang"]] {
+        assert_eq!(glang(&arguments, "").status.code(), Some(2), "{:?}", arguments);
+    }
+    let output = glang(&["--version"], "");
+    assert_eq!(stdout(&output), format!("glang {}\n", env!("CARGO_PKG_VERSION")));
+    assert!(stdout(&glang(&["-h"], "")).starts_with("Usage:"));
+}
+
+#[test]
+fn the_cli_allows_deeper_recursion_than_the_library_default() {
+    let source = "func down(n) { if (n == 0) { return 0 }\nreturn down(n - 1) + 1 }\nprint(down(999))";
+    let output = glang(&["-e", source], "");
+    assert_eq!((output.status.code(), stdout(&output)), (Some(0), "999\n".to_string()), "{}", stderr(&output));
+    let output = glang(&["-e", &source.replace("999", "1000")], "");
+    assert!(stderr(&output).contains("E0309"));
+}
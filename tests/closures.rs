@@ -0,0 +1,95 @@
+mod common;
+
+use common::output;
+use glang::{Interpreter, RuntimeValType};
+use std::rc::Rc;
+
+// References held on the shared array, which every scope that captures it adds to while it is alive
+fn shared_count(interpreter: &Interpreter) -> usize {
+    match interpreter.get_global("shared").unwrap().runtime_val_type {
+        RuntimeValType::Array(elements) => Rc::strong_count(&elements) - 1, // Less the handle get_global just gave out
+        other => panic!("expected an array, got {}", other.type_name())
+    }
+}
+
+#[test]
+fn closures_capture_their_scope() {
+    let source = "
+        func counter() {
+            let count = 0
+            return func() {
+                count += 1
+                return count
+            }
+        }
+        let next = counter()
+        next()
+        print(next(), counter()())
+    ";
+    assert_eq!(output(source).unwrap(), "2 1\n");
+}
+
+#[test]
+fn scopes_that_define_functions_are_freed() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("
+        let shared = [1, 2, 3]
+        func make() {
+            let mine = shared
+            func inner() {
+                return mine
+            }
+            let handlers = {\"inner\": inner, \"anonymous\": [func() { return mine }]}
+            struct Holder { value
+                func get() { return mine }
+            }
+            let holder = Holder { value: 1 }
+            if (true) {
+                let nested = mine
+                let read = func() { return nested }
+            }
+            let outer = 0
+            if (true) {
+                let nested = mine
+                outer = func() { return nested }
+            }
+            return 0
+        }
+    ").unwrap();
+    let before = shared_count(&interpreter);
+    for _ in 0..10 {
+        interpreter.call("make", &[]).unwrap();
+    }
+    assert_eq!(shared_count(&interpreter), before);
+}
+
+#[test]
+fn escaped_closures_keep_their_scope() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("
+        let shared = [1, 2, 3]
+        func make() {
+            let mine = shared
+            func inner() {
+                return len(mine)
+            }
+            return inner
+        }
+        let kept = make()
+        let saved = []
+        func save() {
+            let mine = shared
+            push(saved, func() { return len(mine) })
+        }
+        save()
+        func from_block() {
+            if (true) {
+                let mine = shared
+                return func() { return len(mine) }
+            }
+        }
+        let also_kept = from_block()
+    ").unwrap();
+    assert_eq!(shared_count(&interpreter), 4);
+    assert_eq!(interpreter.eval("kept() + saved[0]() + also_kept()").unwrap().to_string(), "9");
+}
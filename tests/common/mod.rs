@@ -0,0 +1,29 @@
+#![allow(dead_code)] // Each test binary uses a different part of this module
+
+use glang::{GlangError, Interpreter};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+
+// Runs a script and returns what it printed
+pub fn output(source: &str) -> Result<String, GlangError> {
+    let mut interpreter = Interpreter::new();
+    let captured = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(captured.clone());
+    interpreter.eval(source)?;
+    let text = String::from_utf8(captured.borrow().clone()).expect("output is utf-8");
+    Ok(text)
+}
+
+// Runs a script that is expected to fail and returns the error code
+pub fn error_code(source: &str) -> &'static str {
+    match output(source) {
+        Ok(text) => panic!("expected an error, the script printed {:?}", text),
+        Err(error) => error.code
+    }
+}
+
+// Runs a test body on a thread with the stack size of a typical main thread, test threads only get 2 MiB
+pub fn on_main_sized_stack<F: FnOnce() + Send + 'static>(body: F) {
+    thread::Builder::new().stack_size(8 * 1024 * 1024).spawn(body).unwrap().join().unwrap();
+}
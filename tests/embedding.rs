@@ -0,0 +1,95 @@
+use glang::{GlangError, Interpreter, RuntimeVal, RuntimeValType};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn int(value: RuntimeVal) -> i64 {
+    value.expect_int().unwrap()
+}
+
+#[test]
+fn eval_returns_the_last_value_and_keeps_state() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(int(interpreter.eval("let x = 40\nx + 2").unwrap()), 42);
+    assert_eq!(int(interpreter.eval("x += 1\nx").unwrap()), 41);
+    assert_eq!(int(interpreter.eval("return x * 2").unwrap()), 82);
+    assert!(matches!(interpreter.eval("func f() { return 1 }").unwrap().runtime_val_type, RuntimeValType::Null));
+    assert_eq!(interpreter.eval("[1, \"a\"]").unwrap().repr(), "[1, \"a\"]");
+}
+
+#[test]
+fn errors_leave_the_interpreter_usable() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("let total = 1").unwrap();
+    assert_eq!(interpreter.eval("total = total +").unwrap_err().code, "E0202");
+    assert_eq!(interpreter.eval("total = missing").unwrap_err().code, "E0301");
+    assert_eq!(int(interpreter.eval("total + 1").unwrap()), 2);
+}
+
+#[test]
+fn globals_can_be_set_and_read_by_the_host() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("base", 10.into());
+    interpreter.set_global("name", "glang".into());
+    assert_eq!(int(interpreter.eval("base * 2").unwrap()), 20);
+    interpreter.set_global("base", 3.into());
+    interpreter.eval("let seen = format(\"{} {}\", name, base)\nbase += 1").unwrap();
+    assert_eq!(interpreter.get_global("seen").unwrap().to_string(), "glang 3");
+    assert_eq!(int(interpreter.get_global("base").unwrap()), 4);
+    assert!(interpreter.get_global("missing").is_none());
+}
+
+#[test]
+fn script_functions_can_be_called_by_name() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("func scale(x, by) { return x * by }\nlet twice = func(x) { return scale(x, 2) }").unwrap();
+    assert_eq!(int(interpreter.call("scale", &[4.into(), 5.into()]).unwrap()), 20);
+    assert_eq!(int(interpreter.call("twice", &[21.into()]).unwrap()), 42);
+    assert_eq!(interpreter.call("len", &[vec![1.into(), 2.into()].into()]).unwrap().repr(), "2");
+    assert_eq!(interpreter.call("scale", &[1.into()]).unwrap_err().code, "E0305");
+    assert!(interpreter.call("missing", &[]).is_err());
+}
+
+#[test]
+fn reset_forgets_everything_defined_by_scripts() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("let x = 1\nfunc f() { return 2 }\nstruct P { v }").unwrap();
+    interpreter.reset();
+    assert!(interpreter.get_global("x").is_none());
+    assert!(interpreter.call("f", &[]).is_err());
+    interpreter.eval("let x = 3\nfunc f() { return x }\nstruct P { v }").unwrap();
+    assert_eq!(int(interpreter.call("f", &[]).unwrap()), 3);
+}
+
+#[test]
+fn native_functions_are_called_like_script_functions() {
+    let mut interpreter = Interpreter::new();
+    let recorded = Rc::new(RefCell::new(Vec::new()));
+    let sink = recorded.clone();
+    interpreter.register_function("record", Some(2), move |arguments| {
+        sink.borrow_mut().push((arguments[0].expect_str()?.to_string(), arguments[1].expect_float()?));
+        Ok(RuntimeVal::null())
+    });
+    interpreter.register_function("sum", None, |arguments| {
+        let mut total = 0;
+        for argument in arguments {
+            total += argument.expect_int()?;
+        }
+        Ok(total.into())
+    });
+    interpreter.eval("record(\"latency\", sum(1, 2) * 2)\nlet nested = [sum(), sum(5)]\nrecord(\"half\", 0.5)").unwrap();
+    assert_eq!(*recorded.borrow(), vec![("latency".to_string(), 6.0), ("half".to_string(), 0.5)]);
+    assert_eq!(interpreter.get_global("nested").unwrap().repr(), "[0, 5]");
+    assert_eq!(int(interpreter.call("sum", &[1.into(), 2.into(), 3.into()]).unwrap()), 6);
+}
+
+#[test]
+fn native_functions_check_arity_and_pass_errors_back() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_function("half", Some(1), |arguments| Ok((arguments[0].expect_int()? / 2).into()));
+    interpreter.register_function("fail", Some(0), |_| Err(GlangError::runtime("host refused".to_string()).with_code("E0300")));
+    assert_eq!(interpreter.eval("half(1, 2)").unwrap_err().code, "E0305");
+    assert_eq!(interpreter.eval("half(\"x\")").unwrap_err().code, "E0302");
+    assert_eq!(interpreter.eval("fail()").unwrap_err().message, "host refused");
+    interpreter.reset();
+    assert_eq!(int(interpreter.eval("half(9)").unwrap()), 4);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{error_code, on_main_sized_stack, output};
+
+fn recursion(depth: usize) -> String {
+    format!("func down(n) {{\n    if (n == 0) {{\n        return 0\n    }}\n    return down(n - 1) + 1\n}}\nprint(down({}))", depth)
+}
+
+#[test]
+fn default_call_depth_fits_an_eight_mib_stack() {
+    on_main_sized_stack(|| {
+        assert_eq!(output(&recursion(glang::eval::MAX_CALL_DEPTH - 1)).unwrap(), format!("{}\n", glang::eval::MAX_CALL_DEPTH - 1));
+        assert_eq!(error_code(&recursion(glang::eval::MAX_CALL_DEPTH)), "E0309");
+        assert_eq!(error_code("func forever(n) { return forever(n + 1) }\nforever(0)"), "E0309");
+        let busy = format!("struct Walker {{ v\n func walk(n) {{ let i = 0\n loop (i < 1) {{ i += 1\n if (n > 0) {{ return [{{\"k\": self.walk(n - 1)}}][0][\"k\"] + (1 + (1 + (1 + 1))) - 3 }} }}\n return 0 }} }}\nprint(Walker {{ v: 1 }}.walk({}))", glang::eval::MAX_CALL_DEPTH - 1);
+        assert_eq!(output(&busy).unwrap(), format!("{}\n", glang::eval::MAX_CALL_DEPTH - 1));
+    });
+}
+
+#[test]
+fn call_depth_limit_is_configurable() {
+    on_main_sized_stack(|| {
+        let mut interpreter = glang::Interpreter::new();
+        interpreter.eval("func down(n) { if (n == 0) { return 0 }\nreturn down(n - 1) }").unwrap();
+        interpreter.set_max_call_depth(10);
+        assert!(interpreter.call("down", &[9.into()]).is_ok());
+        assert_eq!(interpreter.call("down", &[10.into()]).unwrap_err().code, "E0309");
+        interpreter.set_max_call_depth(glang::eval::MAX_CALL_DEPTH);
+        assert!(interpreter.call("down", &[50.into()]).is_ok());
+    });
+}
+
+#[test]
+fn deep_nesting_is_a_syntax_error() {
+    on_main_sized_stack(|| {
+        let nested = |depth: usize| format!("print({}1{})", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(output(&nested(90)).unwrap(), "1\n");
+        assert_eq!(error_code(&nested(200_000)), "E0204");
+        assert_eq!(error_code(&format!("let a = {}{}", "[".repeat(1000), "]".repeat(1000))), "E0204");
+        assert_eq!(error_code(&format!("{}{}", "{".repeat(1000), "}".repeat(1000))), "E0204");
+        assert_eq!(error_code(&format!("print({}true)", "!".repeat(1000))), "E0204");
+    });
+}
+
+#[test]
+fn assignments_take_a_single_value() {
+    assert_eq!(error_code("let x = 1 2"), "E0201");
+    assert_eq!(error_code("let x = 0\nx = 1 2"), "E0201");
+    assert_eq!(error_code("let a = [0]\na[0] = 1 2"), "E0201");
+    assert_eq!(error_code("struct P { x }\nlet p = P { x: 0 }\np.x = 1 2"), "E0201");
+    assert_eq!(error_code("let x = 0\nx += 1 2"), "E0201");
+    assert_eq!(error_code("let x = 0\nx +="), "E0201");
+    assert_eq!(error_code("let a = [0]\na[0] ="), "E0201");
+    let source = "let a = [0]; a[0] = 5; a[0] += 2\nstruct P { x }\nlet p = P { x: 0 }\nloop (p.x < 3) { p.x = p.x + 1 }\np.x *= 2\nprint(a, p)";
+    assert_eq!(output(source).unwrap(), "[7] P {x: 6}\n");
+}
+
+#[test]
+fn operators_need_a_value_on_the_same_line() {
+    assert_eq!(error_code("let x = 1 +\nprint(x)"), "E0201");
+    assert_eq!(error_code("func f() {\n    return 1 *\n}\nf()"), "E0201");
+    assert_eq!(error_code("print(!\ntrue)"), "E0201");
+    assert_eq!(error_code("let x = 1 +;"), "E0201");
+    assert_eq!(error_code("let x = 1 +"), "E0202");
+    assert_eq!(output("let x = 1 +\n    0 + 2\nprint(x)").unwrap_err().code, "E0201");
+    assert_eq!(output("let x = (1 + 2) *\n    3").unwrap_err().message, "Expected a value after *, got: EOL");
+}
+
+#[test]
+fn errors_point_at_the_offending_source() {
+    let at = |source: &str| {
+        let span = output(source).unwrap_err().span.expect("error has a span");
+        (span.line, span.column, span.length)
+    };
+    assert_eq!(at("let x = 1\nlet y = x / 0"), (2, 9, 5));
+    assert_eq!(at("let x = 1\n\n  print(x +  missing)"), (3, 14, 7));
+    assert_eq!(at("let s = \"héllo\" + 1"), (1, 9, 12)); // Lengths are in bytes
+    assert_eq!(at("let a = [1, 2]\nprint(a[5])"), (2, 7, 4));
+    assert_eq!(at("print(1 @ 2)"), (1, 9, 1));
+    assert_eq!(at("let x = \"open"), (1, 9, 1));
+    assert_eq!(at("func f() {\n    return 1 +\n}"), (2, 14, 1));
+    assert_eq!(at("func f(a) { return a }\nlet y = f(1, 2)"), (2, 9, 7));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{error_code, output};
+
+#[test]
+fn arguments_are_evaluated_expressions() {
+    let source = "
+        func add(a, b, f) {
+            return a + b * f
+        }
+        let x = 4
+        print(add(2, 9, 2), add(x, x + 1, 2), add(add(1, 1, 1), 0, 0))
+    ";
+    assert_eq!(output(source).unwrap(), "20 14 2\n");
+}
+
+#[test]
+fn arity_is_checked() {
+    assert_eq!(error_code("func f(a, b) { return a }\nf(1)"), "E0305");
+    assert_eq!(error_code("func f() { return 1 }\nf(1)"), "E0305");
+    assert_eq!(error_code("len([1], [2])"), "E0305");
+}
+
+#[test]
+fn arguments_and_parameters_may_span_lines() {
+    let source = "
+        func sub(
+            a,
+            b,
+        ) {
+            return a - b
+        }
+        print(sub(
+            5,
+            2,
+        ), sub(9, 4))
+    ";
+    assert_eq!(output(source).unwrap(), "3 5\n");
+    assert_eq!(output("print(func(a, b) { return a * b }(3, 4))").unwrap(), "12\n");
+}
+
+#[test]
+fn arguments_and_parameters_must_be_separated_by_commas() {
+    assert_eq!(error_code("func f(a, b) { return a }\nf(1 2)"), "E0203");
+    assert_eq!(error_code("func f(a, b) { return a }\nf(1\n2)"), "E0203");
+    assert_eq!(error_code("func f(a b) { return a }"), "E0203");
+    assert_eq!(error_code("func f(, a) { return a }"), "E0200");
+    assert_eq!(error_code("func f(a, , b) { return a }"), "E0200");
+    assert_eq!(error_code("print(1 2)"), "E0203");
+    assert_eq!(error_code("print(1\n2)"), "E0203");
+    assert_eq!(error_code("print(1, sep = \"-\" 2)"), "E0203");
+}
+
+#[test]
+fn return_ends_the_function_from_any_depth() {
+    let source = "
+        func first_even(items) {
+            let i = 0
+            loop (i < len(items)) {
+                if (items[i] % 2 == 0) {
+                    return items[i]
+                }
+                i += 1
+            }
+            return -1
+        }
+        func nothing() {
+            return
+        }
+        func implicit() {
+            let x = 1
+        }
+        print(first_even([1, 3, 4, 6]), first_even([1]), nothing(), implicit())
+    ";
+    assert_eq!(output(source).unwrap(), "4 -1 null null\n");
+}
+
+#[test]
+fn calls_are_expressions() {
+    let source = "
+        func square(x) { return x * x }
+        func fact(n) {
+            if (n <= 1) { return 1 }
+            return n * fact(n - 1)
+        }
+        let total = square(3) + square(square(2)) * 2
+        print(total, fact(10), [square(2), fact(3)], square(1) == 1 && fact(0) == 1)
+    ";
+    assert_eq!(output(source).unwrap(), "41 3628800 [4, 6] true\n");
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::{error_code, output};
+
+#[test]
+fn map_literals_and_builtins() {
+    let source = "
+        let config = {\"name\": \"glang\", \"version\": 1, 2: [1, 2]}
+        config[\"version\"] += 1
+        print(config[\"version\"], has(config, \"name\"), has(config, \"missing\"))
+        delete(config, \"name\")
+        print(keys(config), values(config))
+    ";
+    assert_eq!(output(source).unwrap(), "2 true false\n[2, \"version\"] [[1, 2], 2]\n");
+}
+
+#[test]
+fn map_entries_may_span_lines_and_end_with_a_comma() {
+    assert_eq!(output("print({\n    \"a\": 1,\n    \"b\": 2\n    , \"c\": 3,\n})").unwrap(), "{\"a\": 1, \"b\": 2, \"c\": 3}\n");
+    assert_eq!(output("struct P { x\ny }\nprint(P {\n    x: 1,\n    y: 2,\n})").unwrap(), "P {x: 1, y: 2}\n");
+}
+
+#[test]
+fn map_entries_must_be_separated_by_commas() {
+    assert_eq!(error_code("print({\"a\": 1 \"b\": 2})"), "E0203");
+    assert_eq!(error_code("print({\"a\": 1\n\"b\": 2})"), "E0203");
+    assert_eq!(error_code("struct P { x\ny }\nprint(P { x: 1 y: 2 })"), "E0203");
+}
+
+#[test]
+fn missing_keys_are_shown_as_glang_values() {
+    let message = |source: &str| output(source).unwrap_err().message;
+    assert_eq!(message("let m = {\"a\": 1}\nprint(m[\"zz\"])"), "Key not found in map - \"zz\"");
+    assert_eq!(message("let m = {\"a\": 1}\nprint(m[3])"), "Key not found in map - 3");
+    assert_eq!(message("delete({\"a\": 1}, \"zz\")"), "Key not found in map - \"zz\"");
+    assert_eq!(message("delete({\"a\": 1}, true)"), "Key not found in map - true");
+}
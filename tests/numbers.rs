@@ -0,0 +1,75 @@
+mod common;
+
+use common::{error_code, output};
+use glang::{Interpreter, RuntimeValType};
+
+fn value(source: &str) -> RuntimeValType {
+    Interpreter::new().eval(source).unwrap().runtime_val_type
+}
+
+#[test]
+fn mixed_operands_are_promoted_to_float() {
+    assert_eq!(output("print(1 + 2.5, 2.5 * 2, 2 - 0.5, 1 < 1.5, 2 == 2.0, 2.0 != 2)").unwrap(), "3.5 5.0 1.5 true true false\n");
+    assert_eq!(output("let x = 1\nx += 0.5\nprint(x)").unwrap(), "1.5\n");
+    assert!(matches!(value("2 + 2"), RuntimeValType::NumericInteger(4)));
+    assert!(matches!(value("2 + 2.0"), RuntimeValType::NumericFloat(4.0)));
+}
+
+#[test]
+fn division_operators() {
+    assert_eq!(output("print(7 / 2, 6 / 3, 7 ~/ 2, -7 ~/ 2, 7 ~/ -2, 7.5 ~/ 2)").unwrap(), "3.5 2.0 3 -3 -3 3.0\n");
+    assert_eq!(output("print(7 % 2, -7 % 2, 7 % -2, 7.5 % 2)").unwrap(), "1 -1 1 1.5\n");
+    assert_eq!(output("print(2 ** 10, 2 ** -1, 0 ** 0, 4 ** 0.5, (-2) ** 3)").unwrap(), "1024 0.5 1 2.0 -8\n");
+    for source in ["1 / 0", "1 ~/ 0", "1 % 0", "1.0 / 0", "1 ~/ 0.0", "(2 ** 70) % 0"] {
+        assert_eq!(error_code(source), "E0303", "{}", source);
+    }
+}
+
+#[test]
+fn integer_overflow_is_never_silent() {
+    assert_eq!(output("print(9223372036854775807 + 1, -9223372036854775807 - 2)").unwrap(), "9223372036854775808 -9223372036854775809\n");
+    assert_eq!(output("print(3037000500 * 3037000500, 0 - (-9223372036854775807 - 1))").unwrap(), "9223372037000250000 9223372036854775808\n");
+    assert_eq!(output("let x = -9223372036854775807 - 1\nprint(x ~/ -1, x % -1, 0 - x)").unwrap(), "9223372036854775808 0 9223372036854775808\n");
+    assert_eq!(output("let x = 9223372036854775807\nx += 1\nx *= 2\nprint(x)").unwrap(), "18446744073709551616\n");
+}
+
+#[test]
+fn mixed_operand_type_errors() {
+    assert_eq!(error_code("1 + \"a\""), "E0302");
+    assert_eq!(error_code("true * 2"), "E0302");
+    assert_eq!(error_code("[1] - 1"), "E0302");
+}
+
+#[test]
+fn big_integers_turn_back_into_64_bit_integers() {
+    assert!(matches!(value("2 ** 64"), RuntimeValType::BigInteger(_)));
+    assert!(matches!(value("2 ** 64 - 2 ** 64 + 1"), RuntimeValType::NumericInteger(1)));
+    assert!(matches!(value("9223372036854775808 - 1"), RuntimeValType::NumericInteger(i64::MAX)));
+    assert!(matches!(value("(2 ** 100) ~/ (2 ** 30)"), RuntimeValType::BigInteger(_)));
+    assert!(matches!(value("(2 ** 100) ~/ (2 ** 90)"), RuntimeValType::NumericInteger(1024)));
+    assert!(matches!(value("(2 ** 64) % 7"), RuntimeValType::NumericInteger(2)));
+}
+
+#[test]
+fn big_integer_arithmetic() {
+    let source = "
+        let factorial = 1
+        let i = 1
+        loop (i <= 25) {
+            factorial *= i
+            i += 1
+        }
+        print(factorial, factorial ~/ 1000000000000 % 1000, factorial > 9223372036854775807)
+        print((0 - factorial) ~/ 7, (0 - factorial) % 7, factorial / 2 ** 80 > 12.8, 2 ** 64 * 1.0)
+    ";
+    assert_eq!(output(source).unwrap(), "15511210043330985984000000 330 true\n-2215887149047283712000000 0 true 1.8446744073709552e19\n");
+    assert_eq!(output("print(170141183460469231731687303715884105727 % 1000000007, (0 - 2 ** 70) ~/ 3)").unwrap(), "639816141 -393530540239137101141\n");
+}
+
+#[test]
+fn big_integers_that_cannot_be_used() {
+    assert_eq!(error_code("2 ** (2 ** 20)"), "E0308");
+    assert_eq!(error_code("(2 ** 64) & 1"), "E0308");
+    assert_eq!(error_code("let m = {}\nm[2 ** 64] = 1"), "E0308");
+    assert_eq!(error_code("[1][2 ** 64]"), "E0304");
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{error_code, output};
+
+#[test]
+fn compound_assignment_on_variables() {
+    let source = "
+        let x = 10
+        x -= 3
+        x *= 2
+        x /= 4
+        let s = \"a\"
+        s += \"b\"
+        let y = 7
+        y ~/= 2
+        y **= 3
+        let z = y
+        z %= 5
+        print(x, s, y, z)
+    ";
+    assert_eq!(output(source).unwrap(), "3.5 ab 27 2\n");
+    assert_eq!(error_code("missing += 1"), "E0301");
+    assert_eq!(error_code("let s = \"a\"\ns -= 1"), "E0302");
+    assert_eq!(error_code("let x = 1\nx /= 0"), "E0303");
+}
+
+#[test]
+fn compound_assignment_on_elements_and_fields() {
+    let source = "
+        let calls = 0
+        func next() {
+            calls += 1
+            return 0
+        }
+        let a = [1, [2]]
+        a[next()] += 10
+        a[1][0] *= 3
+        let m = {\"n\": 1}
+        m[\"n\"] -= 5
+        struct P { x }
+        let p = P { x: \"a\" }
+        p.x += \"b\"
+        print(a, m, p, calls)
+    ";
+    assert_eq!(output(source).unwrap(), "[11, [6]] {\"n\": -4} P {x: \"ab\"} 1\n");
+    assert_eq!(error_code("let m = {}\nm[\"k\"] += 1"), "E0304");
+}
+
+#[test]
+fn precedence_follows_c() {
+    assert_eq!(output("print(1 + 2 * 3, (1 + 2) * 3, 2 * 3 ** 2, 10 - 4 - 3, 100 / 10 / 5, 7 - 2 * 3 % 4)").unwrap(), "7 9 18 3 2.0 5\n");
+    assert_eq!(output("print(1 < 2 == 2 < 3, 1 + 1 == 2 && 3 > 2, !true || true, !(true || true))").unwrap(), "true true true false\n");
+    assert_eq!(output("print(false && true || true, true || false && false)").unwrap(), "true true\n");
+    assert_eq!(error_code("print(1 < 2 < 3)"), "E0302");
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    let source = "
+        let a = [1, 2]
+        let i = 5
+        let calls = 0
+        func touch(value) {
+            calls += 1
+            return value
+        }
+        print(i < len(a) && a[i] > 0, i >= len(a) || a[i] > 0, touch(false) && touch(true), touch(true) || touch(false), calls)
+    ";
+    assert_eq!(output(source).unwrap(), "false true false true 2\n");
+    assert_eq!(error_code("print(1 && true)"), "E0302");
+    assert_eq!(error_code("print(true && 1)"), "E0302");
+    assert_eq!(error_code("print(!\"a\")"), "E0302");
+}
+
+#[test]
+fn bitwise_operators_on_integers() {
+    assert_eq!(output("print(6 & 3, 6 | 3, 6 ^ 3, ~5, 1 << 62, -16 >> 2, 5 >> 63)").unwrap(), "2 7 5 -6 4611686018427387904 -4 0\n");
+    assert_eq!(output("let b = 6\nb &= 3\nb |= 8\nb ^= 1\nb <<= 2\nb >>= 1\nprint(b)").unwrap(), "22\n");
+    assert_eq!(output("print(1 | 2 ^ 3 & 4, (5 & 4) == 4, 1 << 2 + 1, 1 + 2 << 1 < 7)").unwrap(), "3 true 8 true\n");
+    assert_eq!(error_code("print(1 << 64)"), "E0300");
+    assert_eq!(error_code("print(1 >> -1)"), "E0300");
+    assert_eq!(error_code("print(1.0 & 1)"), "E0302");
+    assert_eq!(error_code("print(true | false)"), "E0302");
+    assert_eq!(error_code("print(~(2 ** 64))"), "E0308");
+    assert_eq!(error_code("print(5 & 4 == 4)"), "E0302"); // As in C, == binds tighter than &
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{error_code, output};
+use glang::Interpreter;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn captured(buffer: &Rc<RefCell<Vec<u8>>>) -> String {
+    String::from_utf8(buffer.borrow().clone()).expect("output is utf-8")
+}
+
+#[test]
+fn print_joins_with_sep_and_ends_with_end() {
+    assert_eq!(output("print(1, \"two\", 3.5)").unwrap(), "1 two 3.5\n");
+    assert_eq!(output("print(1, 2, sep=\", \", end=\"!\")\nprint()").unwrap(), "1, 2!\n");
+    assert_eq!(output("print(\"a\", \"b\", end=\"\", sep=\"\")").unwrap(), "ab");
+    assert_eq!(error_code("print(1, sep=2)"), "E0302");
+}
+
+#[test]
+fn print_and_eprint_go_to_their_own_sinks() {
+    let mut interpreter = Interpreter::new();
+    let out = Rc::new(RefCell::new(Vec::new()));
+    let err = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(out.clone());
+    interpreter.set_error_output(err.clone());
+    interpreter.eval("print(\"to out\")\neprint(\"to\", \"err\", [1, \"x\"])\n{ print(\"from a scope\") }").unwrap();
+    interpreter.eval("func report() { eprint(\"from a function\") }\nreport()").unwrap();
+    assert_eq!(captured(&out), "to out\nfrom a scope\n");
+    assert_eq!(captured(&err), "to err [1, \"x\"]\nfrom a function\n");
+}
+
+#[test]
+fn sinks_survive_a_reset() {
+    let mut interpreter = Interpreter::new();
+    let out = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(out.clone());
+    interpreter.eval("print(1)").unwrap();
+    interpreter.reset();
+    interpreter.eval("print(2)").unwrap();
+    assert_eq!(captured(&out), "1\n2\n");
+}
+
+#[test]
+fn eprint_does_not_take_named_arguments() {
+    let error = output("eprint(\"a\", sep=\"-\")").unwrap_err();
+    assert_eq!(error.code, "E0201");
+    assert_eq!(error.message, "Function calls do not take named arguments");
+    assert_eq!(error_code("func f(a) { return a }\nf(a=1)"), "E0201");
+}
+
+#[test]
+fn values_print_like_literals() {
+    let source = "
+        func f(x) { return x }
+        struct P { x }
+        print(1.0, 1.0 / 4, 10.0 ** 20, true, f, func() {})
+        print(\"top\", [\"nested\\n\", \"q\\\"uote\", 1.5], {\"k\": [P { x: \"s\" }]})
+    ";
+    assert_eq!(output(source).unwrap(), "1.0 0.25 1e20 true <func f> <func anonymous>\ntop [\"nested\\n\", \"q\\\"uote\", 1.5] {\"k\": [P {x: \"s\"}]}\n");
+}
+
+#[test]
+fn containers_that_hold_themselves_print_once() {
+    let source = "
+        let a = [1]
+        push(a, a)
+        let m = {\"self\": 0}
+        m[\"self\"] = m
+        print(a, m, [a])
+    ";
+    assert_eq!(output(source).unwrap(), "[1, [...]] {\"self\": {...}} [[1, [...]]]\n");
+}
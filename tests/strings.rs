@@ -0,0 +1,56 @@
+mod common;
+
+use common::{error_code, output};
+
+#[test]
+fn interpolated_expressions_are_evaluated() {
+    let source = "
+        let name = \"glang\"
+        let items = [1, 2, 3]
+        let m = {\"}\": \"brace\"}
+        print(\"{name} has {len(items)} items, the last is {items[len(items) - 1]}\")
+        print(\"{m[\"}\"]} {{literal}} {1.5 * 2} {[name, true]}\")
+    ";
+    assert_eq!(output(source).unwrap(), "glang has 3 items, the last is 3\nbrace {literal} 3.0 [\"glang\", true]\n");
+}
+
+#[test]
+fn interpolation_errors_point_into_the_string() {
+    let error = output("let x = 1\nprint(\"value: {x + missing}\")").unwrap_err();
+    assert_eq!(error.code, "E0301");
+    let span = error.span.unwrap();
+    assert_eq!((span.line, span.column), (2, 20));
+    assert_eq!(error_code("print(\"{1 2}\")"), "E0201");
+    assert_eq!(error_code("print(\"{}\")\nprint(\"{\")"), "E0103");
+}
+
+#[test]
+fn format_fills_placeholders() {
+    assert_eq!(output("print(format(\"{} + {} = {:>4}|{:<6.2}|{:^7}|\", 1, 2, 3, 1.23456, \"mid\"))").unwrap(), "1 + 2 =    3|1.23  |  mid  |\n");
+    assert_eq!(output("let w = \"{:0>5}\"\nprint(format(w, 42), format(\"{}%\", 5))").unwrap(), "00042 5%\n");
+    assert_eq!(error_code("format(\"{} {}\", 1)"), "E0305");
+    assert_eq!(error_code("format(\"{:q}\", 1)"), "E0300");
+}
+
+#[test]
+fn strings_join_compare_and_index_by_character() {
+    assert_eq!(output("print(\"Hello, \" + \"wörld\"[0].upper() + slice(\"wörld\", 1, 5))").unwrap(), "Hello, Wörld\n");
+    assert_eq!(output("print(\"apple\" < \"banana\", \"b\" >= \"b\", \"a\" == \"a\", \"a\" != \"A\")").unwrap(), "true true true true\n");
+    assert_eq!(output("print(len(\"héllo😀\"), \"héllo😀\"[5], \"héllo\".find(\"l\"), find(\"abc\", \"z\"))").unwrap(), "6 😀 2 -1\n");
+    assert_eq!(error_code("print(\"ab\"[2])"), "E0304");
+    assert_eq!(error_code("print(\"a\" + 1)"), "E0302");
+}
+
+#[test]
+fn string_builtins() {
+    let source = "
+        let csv = \" name, Age ,city \"
+        print(csv.trim().split(\",\").join(\"|\"), split(\"a  b\\tc\"), split(\"héy\", \"\"))
+        print(upper(\"abc\"), lower(\"ÀB\"), replace(\"a-b-c\", \"-\", \"+\"), repeat(\"ab\", 3), repeat(\"x\", 0))
+        print(contains(\"glang\", \"lan\"), starts_with(\"glang\", \"gl\"), ends_with(\"glang\", \"x\"))
+    ";
+    assert_eq!(output(source).unwrap(), "name| Age |city [\"a\", \"b\", \"c\"] [\"h\", \"é\", \"y\"]\nABC àb a+b+c ababab \ntrue true false\n");
+    assert_eq!(error_code("repeat(\"ab\", -1)"), "E0300");
+    assert_eq!(error_code("upper(1)"), "E0302");
+    assert_eq!(output("print(join([\"a\", 1.5, true], \"-\"))").unwrap(), "a-1.5-true\n");
+}
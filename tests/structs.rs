@@ -0,0 +1,51 @@
+mod common;
+
+use common::{error_code, output};
+
+#[test]
+fn fields_and_methods() {
+    let source = "
+        struct Point {
+            x, y
+            func length_squared() {
+                return self.x * self.x + self.y * self.y
+            }
+            func move_by(dx, dy) {
+                self.x += dx
+                self.y = self.y + dy
+                return self
+            }
+        }
+        let p = Point { x: 3, y: 4 }
+        print(p.length_squared(), p.move_by(1, -1).x, p)
+        p.y = 10
+        print(p.y, p.length_squared())
+    ";
+    assert_eq!(output(source).unwrap(), "25 4 Point {x: 4, y: 3}\n10 116\n");
+}
+
+#[test]
+fn instances_are_shared_by_reference() {
+    let source = "
+        struct Box { value }
+        let a = Box { value: 1 }
+        let b = a
+        b.value = 2
+        let boxes = [a]
+        boxes[0].value += 1
+        print(a.value, b)
+    ";
+    assert_eq!(output(source).unwrap(), "3 Box {value: 3}\n");
+    assert_eq!(error_code("struct Box { value }\nlet a = Box { value: 1 }\nprint(a == a)"), "E0302");
+}
+
+#[test]
+fn fields_and_methods_are_checked() {
+    let define = "struct Point { x, y\nfunc norm() { return self.x } }\n";
+    for source in ["Point { x: 1 }", "Point { x: 1, y: 2, z: 3 }", "Point { x: 1, x: 2, y: 3 }", "Point { x: 1, y: 2 }.z", "Point { x: 1, y: 2 }.go()", "let p = Point { x: 1, y: 2 }\np.z = 1"] {
+        assert_eq!(error_code(&format!("{}{}", define, source)), "E0307", "{}", source);
+    }
+    assert_eq!(error_code("Missing { x: 1 }"), "E0301");
+    assert_eq!(error_code(&format!("{}Point {{ x: 1, y: 2 }}.norm(1)", define)), "E0305");
+    assert_eq!(error_code("struct A { x }\nstruct A { y }"), "E0306");
+}
@@ -0,0 +1,105 @@
+use glang::{GlangError, HostObject, Interpreter, RuntimeVal};
+use std::any::Any;
+use std::cell::Cell;
+
+struct Counter {
+    id: i64,
+    count: Cell<i64>
+}
+impl HostObject for Counter {
+    fn type_name(&self) -> &str {
+        "Counter"
+    }
+
+    fn get_field(&self, name: &str) -> Result<RuntimeVal, GlangError> {
+        match name {
+            "count" => Ok(self.count.get().into()),
+            _ => Err(GlangError::runtime(format!("Counter has no field {:?}", name)).with_code("E0307"))
+        }
+    }
+
+    fn set_field(&self, name: &str, value: RuntimeVal) -> Result<(), GlangError> {
+        match name {
+            "count" => {
+                self.count.set(value.expect_int()?);
+                Ok(())
+            },
+            _ => Err(GlangError::runtime(format!("Cannot assign field {:?} of Counter", name)).with_code("E0307"))
+        }
+    }
+
+    fn call_method(&self, name: &str, arguments: &[RuntimeVal]) -> Result<RuntimeVal, GlangError> {
+        match (name, arguments) {
+            ("add", [amount]) => {
+                self.count.set(self.count.get() + amount.expect_int()?);
+                Ok(self.count.get().into())
+            },
+            _ => Err(GlangError::runtime(format!("Counter has no method {:?}", name)).with_code("E0307"))
+        }
+    }
+
+    fn display(&self) -> String {
+        format!("Counter#{}", self.id)
+    }
+
+    fn equals(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Counter>().is_some_and(|other| other.id == self.id)
+    }
+}
+
+// Implements nothing but the type name, so every field and method falls back to the trait defaults
+struct Opaque;
+impl HostObject for Opaque {
+    fn type_name(&self) -> &str {
+        "Opaque"
+    }
+}
+
+fn counter(id: i64) -> RuntimeVal {
+    RuntimeVal::userdata(Counter { id, count: Cell::new(0) })
+}
+
+#[test]
+fn scripts_use_fields_and_methods_and_the_host_sees_the_changes() {
+    let mut interpreter = Interpreter::new();
+    let handle = counter(1);
+    interpreter.set_global("c", handle.clone());
+    interpreter.eval("c.add(5)\nc.count = c.count * 2\nc.count += 1\nlet alias = c\nalias.add(1)").unwrap();
+    assert_eq!(handle.expect_userdata::<Counter>().unwrap().count.get(), 12);
+    assert_eq!(interpreter.eval("format(\"{} {}\", c, [c])").unwrap().to_string(), "Counter#1 [Counter#1]");
+}
+
+#[test]
+fn equality_is_decided_by_the_host_object() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("a", counter(1));
+    interpreter.set_global("same_id", counter(1));
+    interpreter.set_global("other", counter(2));
+    interpreter.set_global("opaque", RuntimeVal::userdata(Opaque));
+    let result = interpreter.eval("[a == a, a == same_id, a == other, opaque == opaque, opaque == a]").unwrap();
+    assert_eq!(result.repr(), "[true, true, false, true, false]");
+    assert_eq!(interpreter.eval("a == 1").unwrap_err().code, "E0302");
+}
+
+#[test]
+fn unsupported_fields_and_methods_are_errors() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("c", counter(1));
+    interpreter.set_global("opaque", RuntimeVal::userdata(Opaque));
+    for source in ["c.missing", "c.missing = 1", "c.reset()", "opaque.x", "opaque.x = 1", "opaque.go()"] {
+        assert_eq!(interpreter.eval(source).unwrap_err().code, "E0307", "{}", source);
+    }
+    assert_eq!(interpreter.eval("c.count = \"x\"").unwrap_err().code, "E0302");
+    assert_eq!(interpreter.eval("format(\"{}\", opaque)").unwrap().to_string(), "<Opaque>");
+}
+
+#[test]
+fn natives_get_their_userdata_back() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_function("count_of", Some(1), |arguments| Ok(arguments[0].expect_userdata::<Counter>()?.count.get().into()));
+    interpreter.set_global("c", counter(1));
+    interpreter.set_global("opaque", RuntimeVal::userdata(Opaque));
+    assert_eq!(interpreter.eval("c.add(3)\ncount_of(c)").unwrap().expect_int().unwrap(), 3);
+    assert_eq!(interpreter.eval("count_of(opaque)").unwrap_err().code, "E0302");
+    assert_eq!(interpreter.eval("count_of(1)").unwrap_err().code, "E0302");
+}